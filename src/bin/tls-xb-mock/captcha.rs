@@ -0,0 +1,62 @@
+use base64::Engine as _;
+use image::{GrayImage, ImageFormat, Luma};
+use std::io::Cursor;
+
+/// 3x5 bitmaps of the digits, one row per string.
+const DIGITS: [[&str; 5]; 10] = [
+    ["###", "#.#", "#.#", "#.#", "###"],
+    [".#.", "##.", ".#.", ".#.", "###"],
+    ["###", "..#", "###", "#..", "###"],
+    ["###", "..#", "###", "..#", "###"],
+    ["#.#", "#.#", "###", "..#", "..#"],
+    ["###", "#..", "###", "..#", "###"],
+    ["###", "#..", "###", "#.#", "###"],
+    ["###", "..#", ".#.", ".#.", ".#."],
+    ["###", "#.#", "###", "#.#", "###"],
+    ["###", "#.#", "###", "..#", "###"],
+];
+const SCALE: u32 = 6;
+const MARGIN: u32 = 8;
+
+/// Draws `answer`, which must be digits, as a PNG captcha in the data URL
+/// format schoolis uses.
+pub fn render(answer: &str) -> String {
+    let digits: Vec<usize> = answer
+        .chars()
+        .filter_map(|c| c.to_digit(10))
+        .map(|digit| digit as usize)
+        .collect();
+    let width = 2 * MARGIN + digits.len() as u32 * 4 * SCALE;
+    let height = 2 * MARGIN + 5 * SCALE;
+    // Light background with a little texture, so it looks like a captcha
+    let mut image = GrayImage::from_fn(width, height, |x, y| {
+        Luma([if (x * 7 + y * 13) % 11 == 0 { 200 } else { 240 }])
+    });
+    for (index, digit) in digits.into_iter().enumerate() {
+        let left = MARGIN + index as u32 * 4 * SCALE;
+        // Alternate the height of the digits a little
+        let top = MARGIN - 2 + (index as u32 % 2) * 4;
+        for (row, pixels) in DIGITS[digit].iter().enumerate() {
+            for (column, pixel) in pixels.chars().enumerate() {
+                if pixel != '#' {
+                    continue;
+                }
+                for dy in 0..SCALE {
+                    for dx in 0..SCALE {
+                        let x = left + column as u32 * SCALE + dx;
+                        let y = top + row as u32 * SCALE + dy;
+                        image.put_pixel(x, y, Luma([40]));
+                    }
+                }
+            }
+        }
+    }
+    let mut png = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .expect("PNG encoding to memory can't fail");
+    format!(
+        "data:image/png;base64,{}",
+        base64::engine::general_purpose::STANDARD.encode(png)
+    )
+}
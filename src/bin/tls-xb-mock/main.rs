@@ -0,0 +1,122 @@
+//! A local schoolis server with synthetic students, for developing and
+//! demoing tls-xb without a school account.
+
+mod captcha;
+mod server;
+mod students;
+
+use clap::Parser;
+use clap_verbosity_flag::{InfoLevel, Verbosity};
+use colored::Colorize;
+use hyper::service::service_fn;
+use hyper_util::rt::TokioIo;
+use log::{info, warn};
+use server::Server;
+use std::{collections::HashMap, error::Error, fs, net::SocketAddr, path::PathBuf, sync::Arc};
+use students::{Students, DEFAULT_STUDENTS};
+use tokio::net::TcpListener;
+
+#[derive(Parser)]
+#[command(
+    version,
+    about = "Mock schoolis server with synthetic students for tls-xb"
+)]
+struct Cli {
+    #[command(flatten)]
+    verbosity: Verbosity<InfoLevel>,
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    listen: SocketAddr,
+    /// TOML file with the students to serve, see --print-students
+    #[arg(long, value_name = "FILE")]
+    students: Option<PathBuf>,
+    /// Print the default students as a starting point for --students
+    #[arg(long)]
+    print_students: bool,
+    /// Digits shown in the login captcha
+    #[arg(long, default_value = "1234", value_parser = parse_captcha)]
+    captcha: String,
+    /// Log in without a captcha
+    #[arg(long, conflicts_with = "captcha")]
+    no_captcha: bool,
+    /// Make an endpoint always fail with a state, e.g. MemberShip/Login=1180038
+    #[arg(long, value_name = "ENDPOINT=STATE", value_parser = parse_failure)]
+    fail: Vec<(String, i32)>,
+}
+
+fn parse_captcha(s: &str) -> Result<String, String> {
+    if s.is_empty() || !s.chars().all(|c| c.is_ascii_digit()) {
+        return Err("the captcha must be digits".to_string());
+    }
+    Ok(s.to_string())
+}
+
+fn parse_failure(s: &str) -> Result<(String, i32), String> {
+    let (endpoint, state) = s
+        .split_once('=')
+        .ok_or_else(|| format!("{s} is not ENDPOINT=STATE"))?;
+    let state = state
+        .parse()
+        .map_err(|_| format!("{state} is not a state"))?;
+    Ok((endpoint.trim_start_matches("/api/").to_string(), state))
+}
+
+fn main() {
+    let cli = Cli::parse();
+    env_logger::Builder::new()
+        .filter_level(cli.verbosity.log_level_filter())
+        .init();
+
+    let result = tokio::runtime::Runtime::new()
+        .map_err(Box::from)
+        .and_then(|runtime| runtime.block_on(run(cli)));
+    if let Err(err) = result {
+        eprintln!("{} {err}", "error:".red().bold());
+        std::process::exit(1);
+    }
+}
+
+async fn run(cli: Cli) -> Result<(), Box<dyn Error>> {
+    if cli.print_students {
+        print!("{DEFAULT_STUDENTS}");
+        return Ok(());
+    }
+    let students = match &cli.students {
+        Some(path) => fs::read_to_string(path)
+            .map_err(|err| format!("failed to read {}: {err}", path.display()))?,
+        None => DEFAULT_STUDENTS.to_string(),
+    };
+    let students = Students::parse(&students)?;
+    let captcha = (!cli.no_captcha).then_some(cli.captcha);
+    let failures: HashMap<String, i32> = cli.fail.into_iter().collect();
+    let server = Arc::new(Server::new(students, captcha, failures));
+
+    let listener = TcpListener::bind(cli.listen).await?;
+    let names: Vec<&str> = server
+        .students
+        .students
+        .iter()
+        .map(|student| student.name.as_str())
+        .collect();
+    info!("Serving {} on http://{}", names.join(", "), cli.listen);
+    match &server.captcha {
+        Some(captcha) => info!("The captcha is {captcha}"),
+        None => info!("Logging in needs no captcha"),
+    }
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let server = Arc::clone(&server);
+        tokio::spawn(async move {
+            let service = service_fn(|request| {
+                let server = Arc::clone(&server);
+                async move { Ok::<_, std::convert::Infallible>(server.handle(request).await) }
+            });
+            if let Err(err) = hyper::server::conn::http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                warn!("Connection failed: {err}");
+            }
+        });
+    }
+}
@@ -0,0 +1,426 @@
+use crate::{
+    captcha,
+    students::{Project, Semester, Student, Students, Subject},
+};
+use chrono::{Datelike, Days, NaiveDate, NaiveTime, Weekday};
+use http_body_util::{BodyExt, Full};
+use hyper::{
+    body::{Bytes, Incoming},
+    header, Method, Request, Response, StatusCode,
+};
+use log::{debug, info};
+use serde_json::{json, Value};
+use std::{
+    collections::{hash_map::RandomState, HashMap},
+    hash::BuildHasher,
+    sync::Mutex,
+};
+
+/// Login states tls-xb knows about.
+const WRONG_CAPTCHA: i32 = 1180038;
+const WRONG_LOGIN: i32 = 1010076;
+
+/// Block times of a school day, the ones `--high-school` moves included.
+const BLOCKS: [(u32, u32, u32, u32); 7] = [
+    (8, 25, 9, 5),
+    (9, 15, 9, 55),
+    (10, 15, 10, 55),
+    (11, 5, 11, 45),
+    (12, 35, 13, 15),
+    (13, 25, 14, 5),
+    (14, 15, 14, 55),
+];
+/// Longest schedule served at once, in days.
+const MAX_SCHEDULE_DAYS: u64 = 366;
+
+pub struct Server {
+    pub students: Students,
+    /// `None` when logging in needs no captcha
+    pub captcha: Option<String>,
+    /// Endpoints that always fail with a state
+    pub failures: HashMap<String, i32>,
+    /// Session cookies and the student they belong to
+    sessions: Mutex<HashMap<String, usize>>,
+}
+
+/// What an endpoint replies.
+enum Reply {
+    Json(Value),
+    /// A session cookie along with the body
+    Login(String, Value),
+    Status(StatusCode),
+}
+
+fn ok(data: Value) -> Reply {
+    Reply::Json(json!({ "state": 0, "msg": null, "data": data }))
+}
+
+fn error(state: i32, msg: &str) -> Reply {
+    Reply::Json(json!({ "state": state, "msg": msg, "data": null }))
+}
+
+fn not_found(what: &str) -> Reply {
+    error(404, &format!("{what} not found"))
+}
+
+/// Parses the query string into its parameters, ignoring malformed ones.
+fn query(query: Option<&str>) -> HashMap<&str, &str> {
+    query
+        .unwrap_or_default()
+        .split('&')
+        .filter_map(|parameter| parameter.split_once('='))
+        .collect()
+}
+
+fn session_cookie(request: &Request<Incoming>) -> Option<String> {
+    let cookies = request.headers().get(header::COOKIE)?.to_str().ok()?;
+    cookies
+        .split(';')
+        .filter_map(|cookie| cookie.trim().strip_prefix("SESSION="))
+        .map(str::to_string)
+        .next()
+}
+
+fn date_time(date: NaiveDate) -> String {
+    date.format("%Y-%m-%dT00:00:00").to_string()
+}
+
+impl Server {
+    pub fn new(
+        students: Students,
+        captcha: Option<String>,
+        failures: HashMap<String, i32>,
+    ) -> Self {
+        Server {
+            students,
+            captcha,
+            failures,
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub async fn handle(&self, request: Request<Incoming>) -> Response<Full<Bytes>> {
+        let method = request.method().clone();
+        let uri = request.uri().clone();
+        let session = session_cookie(&request);
+        let body = match request.into_body().collect().await {
+            Ok(body) => body.to_bytes(),
+            Err(_) => return status(StatusCode::BAD_REQUEST),
+        };
+        let body: Value = serde_json::from_slice(&body).unwrap_or_default();
+        let Some(endpoint) = uri.path().strip_prefix("/api/") else {
+            return status(StatusCode::NOT_FOUND);
+        };
+        let reply = self.reply(&method, endpoint, query(uri.query()), &body, session);
+        match reply {
+            Reply::Json(body) => {
+                info!("{method} {endpoint}: state {}", body["state"]);
+                json_response(&body)
+            }
+            Reply::Login(session, body) => {
+                info!("{method} {endpoint}: logged in");
+                let mut response = json_response(&body);
+                let cookie = format!("SESSION={session}; Path=/; HttpOnly");
+                response
+                    .headers_mut()
+                    .insert(header::SET_COOKIE, cookie.parse().unwrap());
+                response
+            }
+            Reply::Status(code) => {
+                info!("{method} {endpoint}: {code}");
+                status(code)
+            }
+        }
+    }
+
+    fn reply(
+        &self,
+        method: &Method,
+        endpoint: &str,
+        query: HashMap<&str, &str>,
+        body: &Value,
+        session: Option<String>,
+    ) -> Reply {
+        if let Some(state) = self.failures.get(endpoint) {
+            return error(*state, "Failure requested with --fail");
+        }
+        match (method, endpoint) {
+            (&Method::GET, "MemberShip/GetStudentCaptchaForLogin") => {
+                ok(self.captcha.as_deref().map(captcha::render).into())
+            }
+            (&Method::POST, "MemberShip/Login") => self.login(&query, body),
+            _ => {
+                let student = session.and_then(|session| {
+                    let sessions = self.sessions.lock().unwrap();
+                    sessions.get(&session).copied()
+                });
+                // The real server also rejects expired sessions with 401
+                let Some(student) = student else {
+                    return Reply::Status(StatusCode::UNAUTHORIZED);
+                };
+                route(
+                    &self.students.students[student],
+                    method,
+                    endpoint,
+                    &query,
+                    body,
+                )
+            }
+        }
+    }
+
+    fn login(&self, query: &HashMap<&str, &str>, body: &Value) -> Reply {
+        if let Some(answer) = &self.captcha {
+            let captcha = query.get("captcha").copied().unwrap_or_default();
+            if !captcha.eq_ignore_ascii_case(answer) {
+                debug!("Wrong captcha {captcha:?}, expected {answer:?}");
+                return error(WRONG_CAPTCHA, "Wrong captcha");
+            }
+        }
+        let name = body["name"].as_str().unwrap_or_default();
+        let password = body["password"].as_str().unwrap_or_default();
+        let timestamp = body["timestamp"].as_u64().unwrap_or_default();
+        let Some(student) = self.students.login(name, password, timestamp) else {
+            return error(WRONG_LOGIN, "Wrong user name or password");
+        };
+        // RandomState is seeded randomly for every instance
+        let session = format!("{:016x}", RandomState::new().hash_one(student));
+        self.sessions
+            .lock()
+            .unwrap()
+            .insert(session.clone(), student);
+        Reply::Login(session, json!({ "state": 0, "msg": null, "data": null }))
+    }
+}
+
+/// Replies to the endpoints that need a session.
+fn route(
+    student: &Student,
+    method: &Method,
+    endpoint: &str,
+    query: &HashMap<&str, &str>,
+    body: &Value,
+) -> Reply {
+    let id = |key: &str| query.get(key).and_then(|id| id.parse::<u64>().ok());
+    match (method, endpoint) {
+        (&Method::GET, "School/GetSchoolSemesters") => semesters(student),
+        (&Method::GET, "LearningTask/GetStuSubjectListForSelect") => {
+            match id("semesterId").and_then(|id| student.semester(id)) {
+                Some(semester) => subject_list(semester),
+                None => not_found("semester"),
+            }
+        }
+        (&Method::GET, "LearningTask/GetList") => {
+            let subject = id("semesterId")
+                .and_then(|id| student.semester(id))
+                .zip(id("subjectId"))
+                .and_then(|(semester, id)| semester.subject(id));
+            match subject {
+                Some(subject) => task_list(subject),
+                None => not_found("subject"),
+            }
+        }
+        (&Method::GET, "LearningTask/GetDetail") => {
+            match id("learningTaskId").and_then(|id| student.subject_of_task(id)) {
+                Some((semester, subject)) => task_detail(semester, subject),
+                None => not_found("learning task"),
+            }
+        }
+        (&Method::GET, "DynamicScore/GetDynamicScoreDetail") => {
+            let subject = id("semesterId")
+                .and_then(|id| student.semester(id))
+                .and_then(|semester| {
+                    let class_id = id("classId")?;
+                    semester
+                        .subjects
+                        .iter()
+                        .find(|subject| subject.class_id == class_id)
+                });
+            match subject {
+                Some(subject) => dynamic_score_detail(subject),
+                None => not_found("class"),
+            }
+        }
+        (&Method::GET, "DynamicScore/GetStuSemesterDynamicScore") => {
+            match id("semesterId").and_then(|id| student.semester(id)) {
+                Some(semester) => semester_dynamic_score(semester),
+                None => not_found("semester"),
+            }
+        }
+        (&Method::GET, "DynamicScore/GetGpa") => {
+            match id("semesterId").and_then(|id| student.semester(id)) {
+                Some(semester) => ok(semester.gpa().into()),
+                None => not_found("semester"),
+            }
+        }
+        (&Method::POST, "Schedule/ListScheduleByParent") => schedule(student, body),
+        _ => Reply::Status(StatusCode::NOT_FOUND),
+    }
+}
+
+fn json_response(body: &Value) -> Response<Full<Bytes>> {
+    Response::builder()
+        .header(header::CONTENT_TYPE, "application/json; charset=utf-8")
+        .body(Full::new(Bytes::from(body.to_string())))
+        .unwrap()
+}
+
+fn status(code: StatusCode) -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(code)
+        .body(Full::new(Bytes::new()))
+        .unwrap()
+}
+
+fn semesters(student: &Student) -> Reply {
+    let semesters: Vec<Value> = student
+        .semesters
+        .iter()
+        .map(|semester| {
+            json!({
+                "id": semester.id,
+                "year": semester.year,
+                "semester": semester.semester,
+                "isNow": student.is_now(semester),
+                "startDate": date_time(semester.start_date),
+                "endDate": date_time(semester.end_date),
+            })
+        })
+        .collect();
+    ok(semesters.into())
+}
+
+fn subject_list(semester: &Semester) -> Reply {
+    let subjects: Vec<Value> = semester
+        .subjects
+        .iter()
+        .map(|subject| json!({ "id": subject.subject_id, "name": subject.name }))
+        .collect();
+    ok(subjects.into())
+}
+
+/// The first learning task of the subject, tls-xb only needs one.
+fn task_list(subject: &Subject) -> Reply {
+    let tasks: Vec<Value> = subject
+        .tasks()
+        .take(1)
+        .map(|task| json!({ "id": task.id, "name": task.name }))
+        .collect();
+    ok(json!({ "list": tasks, "totalCount": subject.tasks().count() }))
+}
+
+fn task_detail(semester: &Semester, subject: &Subject) -> Reply {
+    ok(json!({
+        "subjectName": subject.name,
+        "classId": subject.class_id,
+        "subjectId": subject.subject_id,
+        "schoolSemesterId": semester.id,
+    }))
+}
+
+fn project_json(subject: &Subject, project: &Project) -> Value {
+    let score = project.score();
+    let (score_level, gpa) = score
+        .map(|score| subject.level_of(score))
+        .unwrap_or_default();
+    let tasks: Vec<Value> = project
+        .tasks
+        .iter()
+        .map(|task| {
+            json!({
+                "id": task.id,
+                "name": task.name,
+                "score": task.score,
+                "totalScore": task.total,
+            })
+        })
+        .collect();
+    let projects: Vec<Value> = project
+        .projects
+        .iter()
+        .map(|project| project_json(subject, project))
+        .collect();
+    json!({
+        "evaluationProjectEName": project.name,
+        "proportion": project.proportion,
+        "score": score.unwrap_or_default(),
+        "scoreLevel": score_level,
+        "gpa": gpa,
+        "scoreIsNull": score.is_none(),
+        "learningTaskAndExamList": tasks,
+        "evaluationProjectList": projects,
+    })
+}
+
+fn dynamic_score_detail(subject: &Subject) -> Reply {
+    let projects: Vec<Value> = subject
+        .projects
+        .iter()
+        .map(|project| project_json(subject, project))
+        .collect();
+    ok(json!({ "evaluationProjectList": projects }))
+}
+
+fn semester_dynamic_score(semester: &Semester) -> Reply {
+    let subjects: Vec<Value> = semester
+        .subjects
+        .iter()
+        .map(|subject| {
+            json!({
+                "classId": subject.class_id,
+                "className": subject.class_name(),
+                "subjectId": subject.subject_id,
+                "subjectName": subject.name,
+                "isInGrade": subject.in_gpa,
+                "subjectScore": subject.score(),
+                "scoreMappingId": subject.score_mapping_id(),
+                "subjectTotalScore": 100.0,
+            })
+        })
+        .collect();
+    ok(json!({ "studentSemesterDynamicScoreBasicDtos": subjects }))
+}
+
+/// Blocks of every school day in the requested range, rotating through the
+/// subjects of the semester each day is in.
+fn schedule(student: &Student, body: &Value) -> Reply {
+    let date = |key: &str| {
+        body[key]
+            .as_str()
+            .and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok())
+    };
+    let (Some(begin), Some(end)) = (date("beginTime"), date("endTime")) else {
+        return error(400, "beginTime and endTime must be YYYY-MM-DD");
+    };
+    let end = end.min(begin + Days::new(MAX_SCHEDULE_DAYS));
+    let mut blocks = Vec::new();
+    for day in begin.iter_days().take_while(|day| *day <= end) {
+        if matches!(day.weekday(), Weekday::Sat | Weekday::Sun) {
+            continue;
+        }
+        let Some(semester) = student.semester_on(day) else {
+            continue;
+        };
+        let subjects = &semester.subjects;
+        if subjects.is_empty() {
+            continue;
+        }
+        let offset = day.num_days_from_ce() as usize;
+        for (index, (begin_hour, begin_minute, end_hour, end_minute)) in
+            BLOCKS.into_iter().enumerate()
+        {
+            let subject = &subjects[(offset + index) % subjects.len()];
+            let time = |hour, minute| {
+                let time = NaiveTime::from_hms_opt(hour, minute, 0).unwrap();
+                day.and_time(time).format("%Y-%m-%dT%H:%M:%S").to_string()
+            };
+            blocks.push(json!({
+                "id": subject.class_id,
+                "eName": subject.class_name(),
+                "beginTime": time(begin_hour, begin_minute),
+                "endTime": time(end_hour, end_minute),
+            }));
+        }
+    }
+    ok(blocks.into())
+}
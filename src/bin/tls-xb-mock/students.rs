@@ -0,0 +1,371 @@
+use chrono::{Local, NaiveDate};
+use serde::{de, Deserialize, Deserializer};
+use std::{collections::HashMap, sync::LazyLock};
+
+/// The students served when no file is given, see `students.toml` for the
+/// fields.
+pub const DEFAULT_STUDENTS: &str = include_str!("students.toml");
+
+#[derive(Deserialize)]
+pub struct Students {
+    #[serde(rename = "student")]
+    pub students: Vec<Student>,
+}
+
+#[derive(Deserialize)]
+pub struct Student {
+    pub name: String,
+    pub password: String,
+    #[serde(rename = "semester")]
+    pub semesters: Vec<Semester>,
+}
+
+#[derive(Deserialize)]
+pub struct Semester {
+    #[serde(skip)]
+    pub id: u64,
+    pub year: u64,
+    pub semester: u64,
+    #[serde(deserialize_with = "toml_date")]
+    pub start_date: NaiveDate,
+    #[serde(deserialize_with = "toml_date")]
+    pub end_date: NaiveDate,
+    #[serde(default = "default_true")]
+    pub gpa_released: bool,
+    #[serde(rename = "subject", default)]
+    pub subjects: Vec<Subject>,
+}
+
+#[derive(Deserialize)]
+pub struct Subject {
+    #[serde(skip)]
+    pub subject_id: u64,
+    #[serde(skip)]
+    pub class_id: u64,
+    pub name: String,
+    class_name: Option<String>,
+    #[serde(default)]
+    pub weighted: bool,
+    #[serde(default)]
+    pub elective: bool,
+    #[serde(default = "default_true")]
+    pub in_gpa: bool,
+    weight: Option<f64>,
+    #[serde(default)]
+    extra_credit: f64,
+    #[serde(rename = "project", default)]
+    pub projects: Vec<Project>,
+}
+
+#[derive(Deserialize)]
+pub struct Project {
+    pub name: String,
+    pub proportion: f64,
+    #[serde(default)]
+    weighting: Weighting,
+    #[serde(default)]
+    drop_lowest: usize,
+    #[serde(default)]
+    pub tasks: Vec<Task>,
+    #[serde(rename = "project", default)]
+    pub projects: Vec<Project>,
+}
+
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum Weighting {
+    /// Every task's percentage counts the same
+    #[default]
+    Equal,
+    /// Tasks count by their total score
+    Points,
+}
+
+#[derive(Deserialize)]
+pub struct Task {
+    #[serde(skip)]
+    pub id: u64,
+    pub name: String,
+    pub score: Option<f64>,
+    pub total: f64,
+}
+
+/// Reads a TOML local date, such as `2025-08-18`.
+fn toml_date<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NaiveDate, D::Error> {
+    let date = toml::value::Datetime::deserialize(deserializer)?;
+    date.to_string().parse().map_err(de::Error::custom)
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn round(value: f64, decimals: i32) -> f64 {
+    let factor = 10f64.powi(decimals);
+    (value * factor).round() / factor
+}
+
+/// Mean of the released scores, weighted by their proportion.
+fn weighted_mean(scores: impl Iterator<Item = (Option<f64>, f64)>) -> Option<f64> {
+    let (total, proportion) = scores
+        .filter_map(|(score, proportion)| Some((score? * proportion, proportion)))
+        .fold((0.0, 0.0), |(total, sum), (score, proportion)| {
+            (total + score, sum + proportion)
+        });
+    (proportion > 0.0).then(|| total / proportion)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ScoreMapping {
+    display_name: String,
+    min_value: f64,
+    max_value: f64,
+    gpa: f64,
+}
+
+/// The bundled score mapping lists, the same ones tls-xb uses.
+static SCORE_MAPPINGS: LazyLock<HashMap<String, Vec<ScoreMapping>>> = LazyLock::new(|| {
+    let mut score_mappings: HashMap<String, serde_json::Value> =
+        serde_json::from_str(include_str!("../../score_mapping_configs.json")).unwrap();
+    ["weighted", "non-weighted"]
+        .into_iter()
+        .map(|name| {
+            let list = serde_json::from_value(score_mappings.remove(name).unwrap()).unwrap();
+            (name.to_string(), list)
+        })
+        .collect()
+});
+
+/// The bundled score mapping ids of the weighted and non-weighted lists,
+/// so tls-xb resolves them without inferring.
+static SCORE_MAPPING_IDS: LazyLock<HashMap<String, u64>> = LazyLock::new(|| {
+    let score_mappings: serde_json::Value =
+        serde_json::from_str(include_str!("../../score_mapping_configs.json")).unwrap();
+    let ids: HashMap<String, String> =
+        serde_json::from_value(score_mappings["ids"].clone()).unwrap();
+    ids.into_iter()
+        .map(|(id, name)| (name, id.parse().unwrap()))
+        .collect()
+});
+
+impl Students {
+    pub fn parse(toml: &str) -> Result<Students, toml::de::Error> {
+        let mut students: Students = toml::from_str(toml)?;
+        students.assign_ids();
+        Ok(students)
+    }
+
+    /// Semester ids are shared by every student, like the school's.
+    /// Subjects keep their id across semesters and classes across the
+    /// semesters of an academic year, while every task gets its own.
+    fn assign_ids(&mut self) {
+        let mut subject_ids = HashMap::new();
+        let mut class_ids = HashMap::new();
+        let mut next_task_id = 500_000;
+        for (student, semester) in
+            self.students
+                .iter_mut()
+                .enumerate()
+                .flat_map(|(index, student)| {
+                    student
+                        .semesters
+                        .iter_mut()
+                        .map(move |semester| (index, semester))
+                })
+        {
+            semester.id = semester.year * 10 + semester.semester;
+            for subject in &mut semester.subjects {
+                let next_subject_id = 100 + subject_ids.len() as u64;
+                subject.subject_id = *subject_ids
+                    .entry(subject.name.clone())
+                    .or_insert(next_subject_id);
+                let next_class_id = 10_000 + class_ids.len() as u64;
+                subject.class_id = *class_ids
+                    .entry((student, semester.year, subject.subject_id))
+                    .or_insert(next_class_id);
+                let mut projects: Vec<&mut Project> = subject.projects.iter_mut().collect();
+                while let Some(project) = projects.pop() {
+                    for task in &mut project.tasks {
+                        task.id = next_task_id;
+                        next_task_id += 1;
+                    }
+                    projects.extend(project.projects.iter_mut());
+                }
+            }
+        }
+    }
+
+    /// The student whose password, hashed with `timestamp` the way tls-xb
+    /// does, is `hashed_password`.
+    pub fn login(&self, name: &str, hashed_password: &str, timestamp: u64) -> Option<usize> {
+        self.students.iter().position(|student| {
+            let hash = format!("{:X}", md5::compute(&student.password));
+            let expected = format!("{:X}", md5::compute(format!("{hash}{timestamp}")));
+            student.name == name && expected.eq_ignore_ascii_case(hashed_password)
+        })
+    }
+}
+
+impl Student {
+    pub fn semester(&self, id: u64) -> Option<&Semester> {
+        self.semesters.iter().find(|semester| semester.id == id)
+    }
+
+    /// The semester containing `date`, or the latest one if none does, so the
+    /// default students stay current.
+    pub fn semester_on(&self, date: NaiveDate) -> Option<&Semester> {
+        self.semesters
+            .iter()
+            .find(|semester| semester.contains(date))
+            .or_else(|| self.semesters.iter().max_by_key(|semester| semester.id))
+    }
+
+    pub fn is_now(&self, semester: &Semester) -> bool {
+        self.semester_on(Local::now().date_naive())
+            .is_some_and(|current| current.id == semester.id)
+    }
+
+    pub fn subjects(&self) -> impl Iterator<Item = (&Semester, &Subject)> {
+        self.semesters.iter().flat_map(|semester| {
+            semester
+                .subjects
+                .iter()
+                .map(move |subject| (semester, subject))
+        })
+    }
+
+    /// The subject a learning task belongs to.
+    pub fn subject_of_task(&self, task_id: u64) -> Option<(&Semester, &Subject)> {
+        self.subjects()
+            .find(|(_, subject)| subject.tasks().any(|task| task.id == task_id))
+    }
+}
+
+impl Semester {
+    fn contains(&self, date: NaiveDate) -> bool {
+        self.start_date <= date && date <= self.end_date
+    }
+
+    pub fn subject(&self, subject_id: u64) -> Option<&Subject> {
+        self.subjects
+            .iter()
+            .find(|subject| subject.subject_id == subject_id)
+    }
+
+    /// The official GPA, `None` while the school hides it.
+    pub fn gpa(&self) -> Option<f64> {
+        if !self.gpa_released {
+            return None;
+        }
+        let gpas = self
+            .subjects
+            .iter()
+            .filter(|subject| subject.in_gpa)
+            .map(|subject| (subject.level().map(|(_, gpa)| gpa), subject.weight()));
+        weighted_mean(gpas).map(|gpa| round(gpa, 2))
+    }
+}
+
+impl Subject {
+    pub fn class_name(&self) -> String {
+        match &self.class_name {
+            Some(class_name) => class_name.clone(),
+            // tls-xb recognizes electives by their class name
+            None if self.elective => format!("{} Ele", self.name),
+            None => self.name.clone(),
+        }
+    }
+
+    fn weight(&self) -> f64 {
+        let default = if self.elective { 0.5 } else { 1.0 };
+        self.weight.unwrap_or(default)
+    }
+
+    pub fn score_mapping_id(&self) -> u64 {
+        SCORE_MAPPING_IDS[self.score_mapping_name()]
+    }
+
+    fn score_mapping_name(&self) -> &'static str {
+        if self.weighted {
+            "weighted"
+        } else {
+            "non-weighted"
+        }
+    }
+
+    pub fn score(&self) -> Option<f64> {
+        let projects = self
+            .projects
+            .iter()
+            .map(|project| (project.score(), project.proportion));
+        weighted_mean(projects).map(|score| round(score + self.extra_credit, 1))
+    }
+
+    /// The level and GPA of a score with this subject's score mapping list.
+    pub fn level_of(&self, score: f64) -> (String, f64) {
+        let score = round(score, 1);
+        SCORE_MAPPINGS[self.score_mapping_name()]
+            .iter()
+            .find(|level| level.min_value <= score && score <= level.max_value)
+            .map(|level| (level.display_name.clone(), level.gpa))
+            .unwrap_or_default()
+    }
+
+    pub fn level(&self) -> Option<(String, f64)> {
+        self.score().map(|score| self.level_of(score))
+    }
+
+    pub fn tasks(&self) -> impl Iterator<Item = &Task> {
+        let mut projects: Vec<&Project> = self.projects.iter().collect();
+        std::iter::from_fn(move || {
+            let project = projects.pop()?;
+            projects.extend(&project.projects);
+            Some(project.tasks.iter())
+        })
+        .flatten()
+    }
+}
+
+impl Project {
+    /// The score out of 100, from the sub-projects if there are any,
+    /// otherwise from the released tasks. `None` while nothing is released.
+    pub fn score(&self) -> Option<f64> {
+        if !self.projects.is_empty() {
+            let projects = self
+                .projects
+                .iter()
+                .map(|project| (project.score(), project.proportion));
+            return weighted_mean(projects).map(|score| round(score, 1));
+        }
+        let mut tasks: Vec<&Task> = self
+            .tasks
+            .iter()
+            .filter(|task| task.score.is_some())
+            .collect();
+        if tasks.len() > self.drop_lowest {
+            tasks.sort_by(|a, b| a.percentage().total_cmp(&b.percentage()));
+            tasks.drain(..self.drop_lowest);
+        }
+        if tasks.is_empty() {
+            return None;
+        }
+        let score = match self.weighting {
+            Weighting::Equal => {
+                tasks.iter().map(|task| task.percentage()).sum::<f64>() / tasks.len() as f64
+            }
+            Weighting::Points => {
+                let score: f64 = tasks.iter().filter_map(|task| task.score).sum();
+                let total: f64 = tasks.iter().map(|task| task.total).sum();
+                score / total * 100.0
+            }
+        };
+        Some(round(score, 1))
+    }
+}
+
+impl Task {
+    fn percentage(&self) -> f64 {
+        self.score.unwrap_or(0.0) / self.total * 100.0
+    }
+}
@@ -0,0 +1,169 @@
+use crate::{
+    client::{ApiError, SchoolisApi},
+    config::CacheConfig,
+    error::Error,
+    private,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use directories::ProjectDirs;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    hash::{DefaultHasher, Hash, Hasher},
+    path::PathBuf,
+    sync::Arc,
+};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum CacheMode {
+    /// Use fresh cached responses, fetch and cache the rest
+    Normal,
+    /// Always fetch, and cache what was fetched
+    Refresh,
+    /// Never fetch, use cached responses however old they are
+    Offline,
+}
+
+/// A cached response, stored as `<cache dir>/tls-xb/http/<hash>.json`.
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    key: String,
+    fetched_at: DateTime<Utc>,
+    body: serde_json::Value,
+}
+
+fn cache_dir() -> Result<PathBuf, Error> {
+    let project_dirs = ProjectDirs::from("rs", "", "tls-xb")
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "no home directory"))?;
+    Ok(project_dirs.cache_dir().join("http"))
+}
+
+/// Removes every cached response, e.g. when another student logs in.
+pub fn clear() -> Result<(), Error> {
+    let dir = cache_dir()?;
+    if dir.exists() {
+        info!("Clearing cache in {}", dir.display());
+        fs::remove_dir_all(dir)?;
+    }
+    Ok(())
+}
+
+/// Serves responses from the on-disk cache while they are fresh, and caches
+/// what `inner` returns.
+pub struct CachedApi {
+    inner: Arc<dyn SchoolisApi>,
+    /// Keeps tenants apart
+    base_url: String,
+    config: CacheConfig,
+    mode: CacheMode,
+    dir: PathBuf,
+}
+
+impl CachedApi {
+    pub fn new(
+        inner: Arc<dyn SchoolisApi>,
+        base_url: &str,
+        config: &CacheConfig,
+        mode: CacheMode,
+    ) -> Result<CachedApi, Error> {
+        Ok(CachedApi {
+            inner,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            config: config.clone(),
+            mode,
+            dir: cache_dir()?,
+        })
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        // Only has to be stable between runs of the same build, a changed
+        // hash merely misses the cache
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        self.dir.join(format!("{:016x}.json", hasher.finish()))
+    }
+
+    fn load(&self, key: &str) -> Option<Entry> {
+        let entry = fs::read_to_string(self.path(key)).ok()?;
+        match serde_json::from_str::<Entry>(&entry) {
+            Ok(entry) if entry.key == key => Some(entry),
+            Ok(_) => None,
+            Err(err) => {
+                debug!("Ignoring unreadable cache entry for {key}: {err}");
+                None
+            }
+        }
+    }
+
+    fn store(&self, key: &str, body: &serde_json::Value) -> Result<(), Error> {
+        // Responses hold grades, keep them private like the session
+        private::create_dir_all(&self.dir)?;
+        let entry = Entry {
+            key: key.to_string(),
+            fetched_at: Utc::now(),
+            body: body.clone(),
+        };
+        private::write(&self.path(key), serde_json::to_vec(&entry)?)?;
+        Ok(())
+    }
+
+    async fn fetch<F>(
+        &self,
+        method: &str,
+        path: &str,
+        body: Option<&serde_json::Value>,
+        request: F,
+    ) -> Result<serde_json::Value, ApiError>
+    where
+        F: std::future::Future<Output = Result<serde_json::Value, ApiError>>,
+    {
+        let Some(ttl) = self.config.ttl(path) else {
+            if self.mode == CacheMode::Offline {
+                return Err(ApiError::NotCached(path.to_string()));
+            }
+            return request.await;
+        };
+        let key = match body {
+            Some(body) => format!("{method} {}/{path} {body}", self.base_url),
+            None => format!("{method} {}/{path}", self.base_url),
+        };
+        if self.mode != CacheMode::Refresh {
+            if let Some(entry) = self.load(&key) {
+                let age = (Utc::now() - entry.fetched_at).to_std().unwrap_or_default();
+                if self.mode == CacheMode::Offline || age < ttl {
+                    debug!("{method} {path} from cache, {}s old", age.as_secs());
+                    return Ok(entry.body);
+                }
+            }
+            if self.mode == CacheMode::Offline {
+                return Err(ApiError::NotCached(path.to_string()));
+            }
+        }
+        let response = request.await?;
+        // Errors such as an expired session must not outlive the request
+        if response.get("state").and_then(|state| state.as_i64()) == Some(0) {
+            if let Err(err) = self.store(&key, &response) {
+                warn!("Failed to cache {path}: {err}");
+            }
+        }
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl SchoolisApi for CachedApi {
+    async fn get(&self, path: &str) -> Result<serde_json::Value, ApiError> {
+        self.fetch("GET", path, None, self.inner.get(path)).await
+    }
+
+    async fn post(
+        &self,
+        path: &str,
+        body: &serde_json::Value,
+    ) -> Result<serde_json::Value, ApiError> {
+        self.fetch("POST", path, Some(body), self.inner.post(path, body))
+            .await
+    }
+}
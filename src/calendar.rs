@@ -1,18 +1,135 @@
-use chrono::{DateTime, FixedOffset, NaiveDateTime, Utc};
-use icalendar::{Calendar as ical, Component, Event, EventLike};
+use crate::client::{self, ApiError, SchoolisApi};
+use chrono::{DateTime, Duration, FixedOffset, NaiveDate, NaiveDateTime, Offset, TimeZone, Utc};
+use chrono_tz::{OffsetComponents, OffsetName, Tz, TzOffset};
+use icalendar::{
+    parser::{Component as ParsedComponent, Property as ParsedProperty},
+    Calendar as ical, CalendarDateTime, Component, Event, EventLike,
+};
 use log::debug;
-use serde::de::{Deserializer, Error};
+use serde::de::{self, Deserializer};
 use serde::Deserialize;
 
-pub fn date_parser<'de, D>(deserializer: D) -> Result<DateTime<FixedOffset>, D::Error>
+/// A time of the server, which has no offset, in the school's time zone.
+/// A time skipped by a daylight saving change is read with the offset from
+/// before the change.
+pub fn school_time(tz: Tz, time: NaiveDateTime) -> DateTime<Tz> {
+    match tz.from_local_datetime(&time).earliest() {
+        Some(time) => time,
+        None => {
+            let before = tz.offset_from_utc_datetime(&(time - Duration::days(1)));
+            tz.from_utc_datetime(&(time - before.fix()))
+        }
+    }
+}
+
+/// The current time at the school.
+pub fn school_now(tz: Tz) -> NaiveDateTime {
+    Utc::now().with_timezone(&tz).naive_local()
+}
+
+/// Offsets are told apart by daylight saving time too, a zone may change
+/// both its base offset and its daylight saving time at once.
+fn same_offset(a: &TzOffset, b: &TzOffset) -> bool {
+    a.fix() == b.fix() && a.dst_offset() == b.dst_offset()
+}
+
+/// The times in UTC from `start` to `end` when `tz` changes its offset, with
+/// the offsets before and after. chrono-tz doesn't list its transitions, so
+/// they are found by the day and then narrowed down to the minute.
+fn transitions(
+    tz: Tz,
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+) -> Vec<(NaiveDateTime, TzOffset, TzOffset)> {
+    let offset = |time: NaiveDateTime| tz.offset_from_utc_datetime(&time);
+    let mut transitions = Vec::new();
+    let mut day = start;
+    while day < end {
+        let next_day = day + Duration::days(1);
+        if !same_offset(&offset(day), &offset(next_day)) {
+            let (mut before, mut after) = (day, next_day);
+            while after - before > Duration::minutes(1) {
+                let middle = before + Duration::minutes((after - before).num_minutes() / 2);
+                if same_offset(&offset(middle), &offset(before)) {
+                    before = middle;
+                } else {
+                    after = middle;
+                }
+            }
+            transitions.push((after, offset(before), offset(after)));
+        }
+        day = next_day;
+    }
+    transitions
+}
+
+fn format_offset(offset: FixedOffset) -> String {
+    let seconds = offset.local_minus_utc();
+    let sign = if seconds < 0 { '-' } else { '+' };
+    let minutes = seconds.abs() / 60;
+    format!("{sign}{:02}{:02}", minutes / 60, minutes % 60)
+}
+
+fn property(name: &'static str, value: impl Into<String>) -> ParsedProperty<'static> {
+    ParsedProperty {
+        name: name.into(),
+        val: value.into().into(),
+        params: Vec::new(),
+    }
+}
+
+/// A `STANDARD` or `DAYLIGHT` observance of `to` from `start`, a local
+/// time at the offset `from`.
+fn observance(start: NaiveDateTime, from: FixedOffset, to: &TzOffset) -> ParsedComponent<'static> {
+    let kind = if to.dst_offset().is_zero() {
+        "STANDARD"
+    } else {
+        "DAYLIGHT"
+    };
+    let mut properties = vec![
+        property("DTSTART", start.format("%Y%m%dT%H%M%S").to_string()),
+        property("TZOFFSETFROM", format_offset(from)),
+        property("TZOFFSETTO", format_offset(to.fix())),
+    ];
+    if let Some(abbreviation) = to.abbreviation() {
+        properties.push(property("TZNAME", abbreviation));
+    }
+    ParsedComponent {
+        name: kind.into(),
+        properties,
+        components: Vec::new(),
+    }
+}
+
+/// A `VTIMEZONE` for `tz`, which the events' `TZID` refers to, with every
+/// offset change between the school times `first` and `last`.
+///
+/// icalendar has no type for it, but takes a parsed component. It still
+/// writes a `DTSTAMP` and `UID` into it, which calendar apps ignore.
+fn vtimezone(tz: Tz, first: NaiveDateTime, last: NaiveDateTime) -> ParsedComponent<'static> {
+    // A day either side covers any offset, whole days keep it to the minute
+    let start = (first.date() - Duration::days(1)).into();
+    let end = (last.date() + Duration::days(2)).into();
+    let initial = tz.offset_from_utc_datetime(&start);
+    let mut observances = vec![observance(start + initial.fix(), initial.fix(), &initial)];
+    for (at, from, to) in transitions(tz, start, end) {
+        observances.push(observance(at + from.fix(), from.fix(), &to));
+    }
+    ParsedComponent {
+        name: "VTIMEZONE".into(),
+        properties: vec![property("TZID", tz.name())],
+        components: observances,
+    }
+}
+
+/// Parses the server's times, which are the school's local time without
+/// an offset.
+pub fn date_parser<'de, D>(deserializer: D) -> Result<NaiveDateTime, D::Error>
 where
     D: Deserializer<'de>,
 {
     let s = String::deserialize(deserializer)?;
-    let naive_datetime =
-        NaiveDateTime::parse_from_str(&s, "%Y-%m-%dT%H:%M:%S").map_err(Error::custom)?;
-    let utc8 = FixedOffset::east_opt(8 * 3600).unwrap();
-    Ok(naive_datetime.and_local_timezone(utc8).unwrap())
+    NaiveDateTime::parse_from_str(&s, "%Y-%m-%dT%H:%M:%S").map_err(de::Error::custom)
 }
 
 #[derive(Deserialize)]
@@ -26,55 +143,67 @@ pub struct Block {
     pub id: u64,
     #[serde(rename = "eName")]
     pub class_name: String,
+    /// In the school's time zone
     #[serde(deserialize_with = "date_parser")]
-    pub begin_time: DateTime<FixedOffset>,
+    pub begin_time: NaiveDateTime,
     #[serde(deserialize_with = "date_parser")]
-    pub end_time: DateTime<FixedOffset>,
+    pub end_time: NaiveDateTime,
 }
 
 impl Calendar {
+    /// The blocks from `begin_date` to `end_date`, school dates inclusive.
     pub async fn new(
-        client: &reqwest::Client,
-        begin_time: DateTime<Utc>,
-        end_time: DateTime<Utc>,
+        api: &dyn SchoolisApi,
+        begin_date: NaiveDate,
+        end_date: NaiveDate,
         high_school: bool,
-    ) -> Calendar {
-        let begin_time_payload = begin_time.format("%Y-%m-%d").to_string();
-        let end_time_payload = end_time.format("%Y-%m-%d").to_string();
+    ) -> Result<Calendar, ApiError> {
+        let begin_time_payload = begin_date.format("%Y-%m-%d").to_string();
+        let end_time_payload = end_date.format("%Y-%m-%d").to_string();
         debug!("Calendar range: {begin_time_payload} - {end_time_payload}");
         let payload =
             &serde_json::json!({"beginTime":begin_time_payload,"endTime":end_time_payload});
-        let response: serde_json::Value = client
-            .post("https://tsinglanstudent.schoolis.cn/api/Schedule/ListScheduleByParent")
-            .json(&payload)
-            .send()
-            .await
-            .unwrap()
-            .json()
-            .await
-            .unwrap();
         let mut calendar = Calendar {
-            blocks: serde_json::from_value(response["data"].clone())
-                .expect("Failed to parse calendar"),
+            blocks: client::post(api, "Schedule/ListScheduleByParent", payload).await?,
         };
         if high_school {
             calendar.fix_high_school_blocks();
         }
-        calendar
+        Ok(calendar)
     }
 
-    pub fn export_ical(&self) -> icalendar::Calendar {
+    /// Exports the blocks in the school's time zone, or in UTC if `utc`.
+    pub fn export_ical(&self, tz: Tz, utc: bool) -> String {
         let mut ical = ical::new();
         ical.name("Tsinglan Class Calendar");
+        if !utc {
+            ical.timezone(tz.name());
+            let first = self.blocks.iter().map(|block| block.begin_time).min();
+            let last = self.blocks.iter().map(|block| block.end_time).max();
+            if let (Some(first), Some(last)) = (first, last) {
+                ical.push(vtimezone(tz, first, last));
+            }
+        }
         for block in &self.blocks {
-            let event = Event::new()
-                .summary(&block.class_name)
-                .starts(block.begin_time.with_timezone(&Utc))
-                .ends(block.end_time.with_timezone(&Utc))
-                .done();
-            ical.push(event);
+            let mut event = Event::new();
+            event.summary(&block.class_name);
+            if utc {
+                let utc = |time| school_time(tz, time).with_timezone(&Utc);
+                event
+                    .starts(utc(block.begin_time))
+                    .ends(utc(block.end_time));
+            } else {
+                let local = |date_time| CalendarDateTime::WithTimezone {
+                    date_time,
+                    tzid: tz.name().to_string(),
+                };
+                event
+                    .starts(local(block.begin_time))
+                    .ends(local(block.end_time));
+            }
+            ical.push(event.done());
         }
-        ical.done()
+        ical.done().to_string()
     }
 
     fn fix_high_school_blocks(&mut self) -> &mut Self {
@@ -96,3 +225,89 @@ impl Calendar {
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(time: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(time, "%Y-%m-%dT%H:%M").unwrap()
+    }
+
+    fn calendar(days: &[&str]) -> Calendar {
+        let blocks = days
+            .iter()
+            .map(|day| Block {
+                id: 1,
+                class_name: "AP Calculus BC".to_string(),
+                begin_time: time(&format!("{day}T08:25")),
+                end_time: time(&format!("{day}T09:05")),
+            })
+            .collect();
+        Calendar { blocks }
+    }
+
+    /// The lines of the first `kind` component in `ical`.
+    fn component<'a>(ical: &'a str, kind: &str) -> Vec<&'a str> {
+        ical.split("\r\n")
+            .skip_while(|line| *line != format!("BEGIN:{kind}"))
+            .take_while(|line| *line != format!("END:{kind}"))
+            .collect()
+    }
+
+    #[test]
+    fn exports_local_times_with_a_vtimezone() {
+        let ical = calendar(&["2025-09-01"]).export_ical(Tz::Asia__Tokyo, false);
+        assert!(ical.find("BEGIN:VTIMEZONE").unwrap() < ical.find("BEGIN:VEVENT").unwrap());
+        assert!(component(&ical, "VTIMEZONE").contains(&"TZID:Asia/Tokyo"));
+        let standard = component(&ical, "STANDARD");
+        assert!(standard.contains(&"TZOFFSETFROM:+0900"));
+        assert!(standard.contains(&"TZOFFSETTO:+0900"));
+        assert!(standard.contains(&"TZNAME:JST"));
+        assert!(ical.contains("DTSTART;TZID=Asia/Tokyo:20250901T082500\r\n"));
+    }
+
+    #[test]
+    fn vtimezone_has_daylight_saving_changes() {
+        // New York leaves daylight saving time on 2 November 2025 at 02:00
+        let ical =
+            calendar(&["2025-10-27", "2025-11-03"]).export_ical(Tz::America__New_York, false);
+        let daylight = component(&ical, "DAYLIGHT");
+        assert!(daylight.contains(&"TZOFFSETTO:-0400"));
+        assert!(daylight.contains(&"TZNAME:EDT"));
+        let standard = component(&ical, "STANDARD");
+        assert!(standard.contains(&"DTSTART:20251102T020000"));
+        assert!(standard.contains(&"TZOFFSETFROM:-0400"));
+        assert!(standard.contains(&"TZOFFSETTO:-0500"));
+        assert!(standard.contains(&"TZNAME:EST"));
+        assert!(!ical.contains("20260308"));
+    }
+
+    #[test]
+    fn exports_utc_times() {
+        let ical = calendar(&["2025-10-27", "2025-11-03"]).export_ical(Tz::America__New_York, true);
+        assert!(!ical.contains("VTIMEZONE"));
+        assert!(ical.contains("DTSTART:20251027T122500Z\r\n"));
+        assert!(ical.contains("DTSTART:20251103T132500Z\r\n"));
+        let ical = calendar(&["2025-09-01"]).export_ical(Tz::Asia__Shanghai, true);
+        assert!(ical.contains("DTSTART:20250901T002500Z\r\n"));
+        assert!(ical.contains("DTEND:20250901T010500Z\r\n"));
+    }
+
+    #[test]
+    fn skipped_times_use_the_earlier_offset() {
+        let tz = Tz::America__New_York;
+        // 02:30 doesn't exist on 9 March 2025, clocks go from 02:00 to 03:00
+        let skipped = school_time(tz, time("2025-03-09T02:30"));
+        assert_eq!(skipped.naive_local(), time("2025-03-09T03:30"));
+        let ambiguous = school_time(tz, time("2025-11-02T01:30"));
+        assert_eq!(ambiguous.naive_utc(), time("2025-11-02T05:30"));
+    }
+
+    #[test]
+    fn empty_calendar_has_no_vtimezone() {
+        let ical = calendar(&[]).export_ical(Tz::Asia__Shanghai, false);
+        assert!(ical.contains("X-WR-TIMEZONE:Asia/Shanghai\r\n"));
+        assert!(!ical.contains("VTIMEZONE"));
+    }
+}
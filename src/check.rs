@@ -0,0 +1,416 @@
+use crate::{
+    gpa::{calculate_gpa, gpa_from_score, ScoreMappingConfig, ScoreMappingId},
+    round_score,
+    subject::{get_subject_score, EvaluationProject, Subject, TaskWeighting},
+};
+use itertools::Itertools;
+use serde::Serialize;
+use std::collections::HashMap;
+use tabled::{
+    settings::{object::Rows, Remove, Style},
+    Table,
+};
+
+/// Differences smaller than this are display rounding, not discrepancies.
+const SCORE_TOLERANCE: f64 = 0.05;
+const GPA_TOLERANCE: f64 = 0.005;
+
+/// Where the server's numbers and tls-xb's recalculation disagree.
+#[derive(Serialize)]
+pub struct CheckReport {
+    /// Number of comparisons made
+    pub checks: usize,
+    pub discrepancies: Vec<Discrepancy>,
+}
+
+/// What a discrepancy compares, the server's number against tls-xb's.
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckKind {
+    /// A project score against its tasks
+    ProjectScore,
+    /// A dynamic score against the subject's projects
+    SubjectScore,
+    /// A project GPA against its score
+    ProjectGpa,
+    /// The official GPA against the subjects
+    Gpa,
+}
+
+impl CheckKind {
+    fn tolerance(self) -> f64 {
+        match self {
+            CheckKind::ProjectScore | CheckKind::SubjectScore => SCORE_TOLERANCE,
+            CheckKind::ProjectGpa | CheckKind::Gpa => GPA_TOLERANCE,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            CheckKind::ProjectScore => "Project score vs tasks",
+            CheckKind::SubjectScore => "Dynamic score vs projects",
+            CheckKind::ProjectGpa => "Project GPA",
+            CheckKind::Gpa => "GPA",
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Discrepancy {
+    pub check: CheckKind,
+    pub subject: Option<String>,
+    /// Evaluation project names from the top level down
+    pub path: Vec<String>,
+    pub server: f64,
+    pub calculated: f64,
+    pub difference: f64,
+    pub likely_cause: String,
+}
+
+struct Checker<'a> {
+    score_mapping_lists: &'a HashMap<ScoreMappingId, Vec<ScoreMappingConfig>>,
+    report: CheckReport,
+}
+
+impl Checker<'_> {
+    fn compare(
+        &mut self,
+        check: CheckKind,
+        subject: Option<&Subject>,
+        path: &[String],
+        server: f64,
+        calculated: f64,
+        likely_cause: impl FnOnce(f64) -> String,
+    ) {
+        if !server.is_finite() || !calculated.is_finite() {
+            return;
+        }
+        self.report.checks += 1;
+        let difference = calculated - server;
+        if difference.abs() < check.tolerance() {
+            return;
+        }
+        self.report.discrepancies.push(Discrepancy {
+            check,
+            subject: subject.map(|subject| subject.subject_name.clone()),
+            path: path.to_vec(),
+            server,
+            calculated,
+            difference,
+            likely_cause: likely_cause(difference),
+        });
+    }
+
+    fn check_projects(
+        &mut self,
+        subject: &Subject,
+        path: &[String],
+        evaluation_projects: &[EvaluationProject],
+    ) {
+        for evaluation_project in evaluation_projects {
+            if evaluation_project.score_is_null {
+                continue;
+            }
+            let mut project_path = path.to_vec();
+            project_path.push(evaluation_project.evaluation_project_e_name.clone());
+
+            // Against the plain mean, the inferred weighting reproduces the
+            // server's score by construction, so it can only explain a difference
+            let tasks = &evaluation_project.learning_task_and_exam_list;
+            if let Some(task_mean) = TaskWeighting::EqualPercentage.score(tasks) {
+                let unscored = tasks.iter().filter(|task| task.score.is_none()).count();
+                let task_weighting = evaluation_project.task_weighting;
+                let task_weightings = evaluation_project.reproducing_task_weightings();
+                self.compare(
+                    CheckKind::ProjectScore,
+                    Some(subject),
+                    &project_path,
+                    evaluation_project.score,
+                    task_mean,
+                    |difference| match task_weighting {
+                        Some(task_weighting)
+                            if task_weighting != TaskWeighting::EqualPercentage =>
+                        {
+                            format!("the {task_weighting} task weighting reproduces it")
+                        }
+                        _ if task_weightings.len() > 1 => format!(
+                            "the {} task weightings all reproduce it",
+                            task_weightings.iter().join(" or ")
+                        ),
+                        _ if difference.abs() <= 0.5 => {
+                            "rounding of task or project scores".to_string()
+                        }
+                        _ if unscored > 0 => {
+                            format!("{unscored} task(s) without a score, or hidden tasks")
+                        }
+                        _ => {
+                            "no task weighting reproduces it, there may be hidden tasks".to_string()
+                        }
+                    },
+                );
+            }
+
+            if evaluation_project.gpa.is_finite() {
+                let calculated =
+                    gpa_from_score(evaluation_project.score, &subject.score_mapping_list);
+                let other_list = self
+                    .score_mapping_lists
+                    .iter()
+                    .find(|(score_mapping_list_id, score_mapping_list)| {
+                        **score_mapping_list_id != subject.score_mapping_list_id
+                            && (gpa_from_score(evaluation_project.score, score_mapping_list)
+                                - evaluation_project.gpa)
+                                .abs()
+                                < GPA_TOLERANCE
+                    })
+                    .map(|(score_mapping_list_id, _)| score_mapping_list_id.to_string());
+                self.compare(
+                    CheckKind::ProjectGpa,
+                    Some(subject),
+                    &project_path,
+                    evaluation_project.gpa,
+                    calculated,
+                    |_| match other_list {
+                        Some(other_list) => format!(
+                            "the server maps this project with the {other_list} list, not {}",
+                            subject.score_mapping_list_id
+                        ),
+                        None => {
+                            "score at a grade boundary, or an unknown score mapping".to_string()
+                        }
+                    },
+                );
+            }
+
+            self.check_projects(
+                subject,
+                &project_path,
+                &evaluation_project.evaluation_project_list,
+            );
+        }
+    }
+
+    fn check_subject(&mut self, subject: &Subject) {
+        self.check_projects(subject, &[], &subject.evaluation_projects);
+        // Without a dynamic score the total is the calculated one
+        if subject.score_mapping_id.is_none() {
+            return;
+        }
+        let unreleased = subject
+            .evaluation_projects
+            .iter()
+            .filter(|evaluation_project| evaluation_project.score_is_null)
+            .count();
+        self.compare(
+            CheckKind::SubjectScore,
+            Some(subject),
+            &[],
+            subject.total_score,
+            get_subject_score(&subject.evaluation_projects),
+            |difference| {
+                if difference.abs() <= 0.5 {
+                    "rounding of project scores".to_string()
+                } else if difference < 0.0 {
+                    "extra credit added by the teacher".to_string()
+                } else if unreleased > 0 {
+                    format!("{unreleased} unreleased project(s) counted by the server")
+                } else {
+                    "hidden projects, or a deduction by the teacher".to_string()
+                }
+            },
+        );
+    }
+
+    fn check_gpa(&mut self, subjects: &[Subject], gpa: f64) {
+        let calculated = calculate_gpa(subjects).weighted_gpa;
+        // Try the usual explanations and report the first that matches
+        let matches = |subjects: &[Subject]| {
+            (calculate_gpa(subjects).weighted_gpa - gpa).abs() < GPA_TOLERANCE
+        };
+        let with = |change: &dyn Fn(&mut Subject)| -> Vec<Subject> {
+            subjects
+                .iter()
+                .cloned()
+                .map(|mut subject| {
+                    change(&mut subject);
+                    subject
+                })
+                .collect()
+        };
+        let excluded: Vec<&str> = subjects
+            .iter()
+            .filter(|subject| !subject.in_gpa)
+            .map(|subject| subject.subject_name.as_str())
+            .collect();
+        let left_out = subjects
+            .iter()
+            .filter(|subject| subject.counts_in_gpa())
+            .find(|left_out| {
+                matches(&with(&|subject| {
+                    if subject.class_id == left_out.class_id {
+                        subject.in_gpa = false;
+                    }
+                }))
+            });
+        let likely_cause =
+            if !excluded.is_empty() && matches(&with(&|subject| subject.in_gpa = true)) {
+                format!("the official GPA includes {}", excluded.join(", "))
+            } else if let Some(subject) = left_out {
+                format!("the official GPA leaves out {}", subject.subject_name)
+            } else if matches(&with(&|subject| subject.weight = 1.0)) {
+                "the official GPA weighs every subject fully".to_string()
+            } else {
+                "subject weights, score mappings, or an outdated official GPA".to_string()
+            };
+        self.compare(CheckKind::Gpa, None, &[], gpa, calculated, |_| likely_cause);
+    }
+}
+
+/// Cross-checks the server's project scores, project GPAs, dynamic scores
+/// and GPA against the ones recalculated from tasks and projects.
+pub fn check(
+    subjects: &[Subject],
+    gpa: f64,
+    score_mapping_lists: &HashMap<ScoreMappingId, Vec<ScoreMappingConfig>>,
+) -> CheckReport {
+    let mut checker = Checker {
+        score_mapping_lists,
+        report: CheckReport {
+            checks: 0,
+            discrepancies: Vec::new(),
+        },
+    };
+    for subject in subjects {
+        checker.check_subject(subject);
+    }
+    checker.check_gpa(subjects, gpa);
+    checker.report
+}
+
+impl CheckReport {
+    pub fn print(&self) {
+        if self.discrepancies.is_empty() {
+            println!("All {} checks agree", self.checks);
+            return;
+        }
+        let mut data = vec![(
+            "Check".to_string(),
+            "Where".to_string(),
+            "Server".to_string(),
+            "Calculated".to_string(),
+            "Difference".to_string(),
+            "Likely cause".to_string(),
+        )];
+        for discrepancy in &self.discrepancies {
+            let mut place: Vec<&str> = discrepancy.subject.iter().map(String::as_str).collect();
+            place.extend(discrepancy.path.iter().map(String::as_str));
+            data.push((
+                discrepancy.check.label().to_string(),
+                if place.is_empty() {
+                    "-".to_string()
+                } else {
+                    place.join(" / ")
+                },
+                round_score(discrepancy.server, 2).to_string(),
+                round_score(discrepancy.calculated, 2).to_string(),
+                format!("{:+}", round_score(discrepancy.difference, 2)),
+                discrepancy.likely_cause.clone(),
+            ));
+        }
+        let table = Table::new(data)
+            .with(Remove::row(Rows::first()))
+            .with(Style::rounded())
+            .to_string();
+        println!("{table}");
+        println!(
+            "{} of {} checks disagree",
+            self.discrepancies.len(),
+            self.checks
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gpa::default_score_mapping_lists;
+
+    /// A subject with one project the server scored `score` from two tasks.
+    fn subject(score: f64) -> Subject {
+        Subject::for_tests(
+            "English",
+            vec![
+                EvaluationProject::for_tests("Formative", 100.0, Some(score))
+                    .with_task("Quiz", 5.0, 10.0)
+                    .with_task("Essay", 90.0, 100.0),
+            ],
+        )
+    }
+
+    fn project_scores(subject: &Subject) -> Vec<Discrepancy> {
+        check(
+            std::slice::from_ref(subject),
+            f64::NAN,
+            &default_score_mapping_lists(),
+        )
+        .discrepancies
+        .into_iter()
+        .filter(|discrepancy| discrepancy.check == CheckKind::ProjectScore)
+        .collect()
+    }
+
+    #[test]
+    fn plain_mean_agrees() {
+        assert!(project_scores(&subject(70.0)).is_empty());
+    }
+
+    #[test]
+    fn inferred_weighting_explains_the_difference() {
+        // 95 / 110 points instead of the mean of 50% and 90%
+        let discrepancies = project_scores(&subject(86.36));
+        let [discrepancy] = &discrepancies[..] else {
+            panic!("expected one discrepancy");
+        };
+        assert_eq!(discrepancy.check, CheckKind::ProjectScore);
+        assert_eq!(
+            serde_json::to_value(discrepancy.check).unwrap(),
+            "project_score"
+        );
+        assert_eq!(discrepancy.calculated, 70.0);
+        assert_eq!(
+            discrepancy.likely_cause,
+            "the points-based task weighting reproduces it"
+        );
+    }
+
+    #[test]
+    fn ambiguous_weighting_names_every_candidate() {
+        let subject = Subject::for_tests(
+            "English",
+            vec![EvaluationProject::for_tests("Formative", 100.0, Some(87.0))
+                .with_task("Quiz", 8.0, 10.0)
+                .with_task("Essay", 90.0, 100.0)
+                .with_task("Test", 84.0, 100.0)],
+        );
+        let discrepancies = project_scores(&subject);
+        let [discrepancy] = &discrepancies[..] else {
+            panic!("expected one discrepancy");
+        };
+        assert_eq!(
+            discrepancy.likely_cause,
+            "the points-based or drop lowest 1 task weightings all reproduce it"
+        );
+    }
+
+    #[test]
+    fn unexplained_difference() {
+        let discrepancies = project_scores(&subject(60.0));
+        let [discrepancy] = &discrepancies[..] else {
+            panic!("expected one discrepancy");
+        };
+        assert_eq!(
+            discrepancy.likely_cause,
+            "no task weighting reproduces it, there may be hidden tasks"
+        );
+    }
+}
@@ -1,6 +1,15 @@
-use crate::{config::Login, prompt_input};
+use crate::{config::Login, error::Error, prompt_input};
+use async_trait::async_trait;
 use base64::Engine as _;
-use serde::Serialize;
+use log::{debug, info, warn};
+use reqwest_cookie_store::{CookieStore, CookieStoreMutex};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    fs,
+    io::{BufReader, Write},
+    path::PathBuf,
+    sync::Arc,
+};
 
 #[derive(Serialize)]
 struct Payload {
@@ -13,61 +22,344 @@ pub enum LoginError {
     IncorrectCaptcha(String),
     IncorrectLogin(String),
     ErrorCode((String, i32)),
+    Api(ApiError),
 }
 
-pub async fn login(config: &Login) -> Result<reqwest::Client, LoginError> {
-    let client = reqwest::Client::builder()
-        .cookie_store(true)
-        .build()
-        .unwrap();
+impl From<ApiError> for LoginError {
+    fn from(err: ApiError) -> Self {
+        LoginError::Api(err)
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ApiError {
+    #[error("session expired: {0}")]
+    SessionExpired(String),
+    #[error("permission denied: {0}")]
+    PermissionDenied(String),
+    #[error("not found: {0}")]
+    NotFound(String),
+    #[error("server returned state {state}: {msg}")]
+    Unknown { state: i32, msg: String },
+    #[error("request failed: {0}")]
+    Network(#[from] reqwest::Error),
+    #[error("unexpected response: {0}")]
+    Parse(#[from] serde_json::Error),
+    #[error("{0} is not cached")]
+    NotCached(String),
+    #[error("{0} is not in the recording")]
+    NotRecorded(String),
+    /// Logging in again after the session was rejected failed
+    #[error("{0}")]
+    Relogin(Box<Error>),
+}
+
+/// States of the response envelope, and HTTP statuses of rejected requests,
+/// that tls-xb tells apart. schoolis reuses the HTTP codes in the envelope.
+pub mod state {
+    pub const OK: i32 = 0;
+    /// Not logged in, or the session expired
+    pub const SESSION_EXPIRED: i32 = 401;
+    pub const PERMISSION_DENIED: i32 = 403;
+    pub const NOT_FOUND: i32 = 404;
+    pub const INCORRECT_LOGIN: [i32; 2] = [13, 1010076];
+    pub const INCORRECT_CAPTCHA: i32 = 1180038;
+}
+
+impl ApiError {
+    /// Maps the states that mean something to tls-xb, anything else is
+    /// `Unknown` so it isn't mistaken for an expired session.
+    fn from_state(state: i32, msg: String) -> ApiError {
+        match state {
+            state::SESSION_EXPIRED => ApiError::SessionExpired(msg),
+            state::PERMISSION_DENIED => ApiError::PermissionDenied(msg),
+            state::NOT_FOUND => ApiError::NotFound(msg),
+            _ => ApiError::Unknown { state, msg },
+        }
+    }
+
+    /// The error a response envelope reports, `None` if it succeeded or
+    /// isn't an envelope.
+    pub fn from_body(body: &serde_json::Value) -> Option<ApiError> {
+        let state = i32::try_from(body.get("state")?.as_i64()?).ok()?;
+        let msg = body
+            .get("msg")
+            .and_then(|msg| msg.as_str())
+            .unwrap_or_default();
+        (state != state::OK).then(|| ApiError::from_state(state, msg.to_string()))
+    }
+
+    /// Whether logging in again could fix the request.
+    pub fn needs_login(&self) -> bool {
+        matches!(
+            self,
+            ApiError::SessionExpired(_) | ApiError::PermissionDenied(_)
+        )
+    }
+}
+
+/// The envelope every schoolis endpoint wraps its data in.
+#[derive(Deserialize)]
+pub struct ApiResponse<T> {
+    pub state: i32,
+    #[serde(default)]
+    pub msg: Option<String>,
+    pub data: Option<T>,
+}
+
+impl<T: DeserializeOwned> ApiResponse<T> {
+    pub fn parse(body: serde_json::Value) -> Result<ApiResponse<T>, ApiError> {
+        Ok(serde_json::from_value(body)?)
+    }
+
+    /// Returns the data, or the error described by a non-zero state.
+    pub fn into_data(self) -> Result<T, ApiError> {
+        if self.state != state::OK {
+            return Err(ApiError::from_state(
+                self.state,
+                self.msg.unwrap_or_default(),
+            ));
+        }
+        // Endpoints such as GetGpa return null data for unreleased values
+        match self.data {
+            Some(data) => Ok(data),
+            None => Ok(serde_json::from_value(serde_json::Value::Null)?),
+        }
+    }
+}
+
+/// The schoolis API as seen by the fetchers, so they can run against the real
+/// server, a local mock server or an in-process fake.
+///
+/// Paths are relative to `/api/`, e.g. `School/GetSchoolSemesters`.
+/// Implementations return the raw response body, see [`get`] and [`post`]
+/// for unwrapping it.
+#[async_trait]
+pub trait SchoolisApi: Send + Sync {
+    async fn get(&self, path: &str) -> Result<serde_json::Value, ApiError>;
+    async fn post(
+        &self,
+        path: &str,
+        body: &serde_json::Value,
+    ) -> Result<serde_json::Value, ApiError>;
+}
+
+/// GETs `path` and returns the data of the response envelope.
+pub async fn get<T: DeserializeOwned>(api: &dyn SchoolisApi, path: &str) -> Result<T, ApiError> {
+    ApiResponse::parse(api.get(path).await?)?.into_data()
+}
+
+/// POSTs `body` to `path` and returns the data of the response envelope.
+pub async fn post<T: DeserializeOwned>(
+    api: &dyn SchoolisApi,
+    path: &str,
+    body: &serde_json::Value,
+) -> Result<T, ApiError> {
+    ApiResponse::parse(api.post(path, body).await?)?.into_data()
+}
+
+pub const DEFAULT_BASE_URL: &str = "https://tsinglanstudent.schoolis.cn";
+
+/// HTTP client for a schoolis.cn tenant. Its cookies are persisted to
+/// `session.json` in the configuration directory, so the login survives
+/// between runs.
+pub struct ApiClient {
+    base_url: String,
+    client: reqwest::Client,
+    cookie_store: Arc<CookieStoreMutex>,
+}
+
+impl ApiClient {
+    /// Restores the saved session, or starts an empty one if there is none.
+    pub fn load(base_url: &str) -> Result<ApiClient, Error> {
+        let path = session_path()?;
+        let cookie_store = match fs::File::open(&path) {
+            Ok(file) => {
+                info!("Restoring session from {}", path.display());
+                cookie_store::serde::json::load_all(BufReader::new(file)).unwrap_or_else(|err| {
+                    warn!("Failed to read saved session: {err}");
+                    CookieStore::default()
+                })
+            }
+            Err(_) => CookieStore::default(),
+        };
+        let cookie_store = Arc::new(CookieStoreMutex::new(cookie_store));
+        let client = reqwest::Client::builder()
+            .cookie_provider(Arc::clone(&cookie_store))
+            .build()
+            .map_err(ApiError::Network)?;
+        Ok(ApiClient {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            client,
+            cookie_store,
+        })
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/api/{path}", self.base_url)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cookie_store
+            .lock()
+            .unwrap()
+            .iter_any()
+            .next()
+            .is_none()
+    }
+
+    fn clear(&self) {
+        self.cookie_store.lock().unwrap().clear();
+    }
+
+    /// Writes the session cookies to disk, readable only by the current user.
+    pub fn save(&self) -> Result<(), Error> {
+        let path = session_path()?;
+        debug!("Saving session to {}", path.display());
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(&path)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            // mode() only applies when the file is created
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+        }
+        let cookie_store = self.cookie_store.lock().unwrap();
+        cookie_store::serde::json::save_incl_expired_and_nonpersistent(&cookie_store, &mut file)
+            .map_err(std::io::Error::other)?;
+        file.flush()?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SchoolisApi for ApiClient {
+    async fn get(&self, path: &str) -> Result<serde_json::Value, ApiError> {
+        debug!("GET {path}");
+        let response = self.client.get(self.url(path)).send().await?;
+        read_body(response).await
+    }
+
+    async fn post(
+        &self,
+        path: &str,
+        body: &serde_json::Value,
+    ) -> Result<serde_json::Value, ApiError> {
+        debug!("POST {path}");
+        let response = self.client.post(self.url(path)).json(body).send().await?;
+        read_body(response).await
+    }
+}
+
+async fn read_body(response: reqwest::Response) -> Result<serde_json::Value, ApiError> {
+    let status = response.status();
+    // Rejected requests may not have a JSON body, so check the status first
+    if !status.is_success() {
+        let msg = status.to_string();
+        return Err(ApiError::from_state(status.as_u16().into(), msg));
+    }
+    Ok(serde_json::from_str(&response.text().await?)?)
+}
+
+fn session_path() -> Result<PathBuf, confy::ConfyError> {
+    let login_path = confy::get_configuration_file_path("tls-xb", "login")?;
+    Ok(login_path.with_file_name("session.json"))
+}
+
+pub async fn login(client: &ApiClient, config: &Login) -> Result<(), LoginError> {
+    // Start from a clean cookie jar so a stale session can't interfere
+    client.clear();
     let payload = Payload {
         name: config.name.clone(),
         password: config.password.clone(),
         timestamp: config.timestamp,
     };
-    let captcha = get_captcha(&client).await;
-    let response: serde_json::Value = client
-        .post(format!(
-            "https://tsinglanstudent.schoolis.cn/api/MemberShip/Login?captcha={captcha}",
-        ))
-        .json(&payload)
-        .send()
-        .await
-        .unwrap()
-        .json()
-        .await
-        .unwrap();
-
-    let state = serde_json::from_value(response["state"].clone()).unwrap();
-    match state {
-        0 => Ok(client),
-        1180038 => Err(LoginError::IncorrectCaptcha(response["msg"].to_string())),
-        13 | 1010076 => Err(LoginError::IncorrectLogin(response["msg"].to_string())),
-        _ => Err(LoginError::ErrorCode((response["msg"].to_string(), state))),
-    }
-}
-
-pub async fn get_captcha(client: &reqwest::Client) -> String {
-    let reponse: serde_json::Value = client
-        .get("https://tsinglanstudent.schoolis.cn/api/MemberShip/GetStudentCaptchaForLogin")
-        .send()
-        .await
-        .unwrap()
-        .json()
-        .await
-        .unwrap();
-    let encoded_captcha: String = serde_json::from_value(reponse["data"].clone()).unwrap();
+    let captcha = get_captcha(client).await?;
+    let body = client
+        .post(
+            &format!("MemberShip/Login?captcha={captcha}"),
+            &serde_json::to_value(&payload).unwrap(),
+        )
+        .await?;
+    let response: ApiResponse<serde_json::Value> = ApiResponse::parse(body)?;
+
+    let msg = response.msg.unwrap_or_default();
+    match response.state {
+        state::OK => Ok(()),
+        state::INCORRECT_CAPTCHA => Err(LoginError::IncorrectCaptcha(msg)),
+        state if state::INCORRECT_LOGIN.contains(&state) => Err(LoginError::IncorrectLogin(msg)),
+        state => Err(LoginError::ErrorCode((msg, state))),
+    }
+}
+
+pub async fn get_captcha(api: &dyn SchoolisApi) -> Result<String, ApiError> {
+    let encoded_captcha: Option<String> = get(api, "MemberShip/GetStudentCaptchaForLogin").await?;
+    let encoded_captcha = encoded_captcha.unwrap_or_default();
     if encoded_captcha.is_empty() {
-        return encoded_captcha;
+        return Ok(encoded_captcha);
     }
 
     let decoded_captcha = base64::engine::general_purpose::STANDARD
         .decode(encoded_captcha.trim_start_matches("data:image/png;base64,"))
-        .expect("Failed to decode base64 data");
-    let image = image::load_from_memory(&decoded_captcha).expect("Failed to load image");
+        .map_err(|err| invalid_captcha(&err))?;
+    let image = image::load_from_memory(&decoded_captcha).map_err(|err| invalid_captcha(&err))?;
     let conf = viuer::Config::default();
     print!("\x1B[2J"); // clear terminal screen
-    viuer::print(&image, &conf).expect("Failed to print image");
+    if let Err(err) = viuer::print(&image, &conf) {
+        warn!("Failed to print captcha: {err}");
+    }
+
+    Ok(prompt_input!("\nCaptacha: "))
+}
+
+fn invalid_captcha(err: &dyn std::error::Error) -> ApiError {
+    ApiError::Parse(serde::de::Error::custom(format!("invalid captcha: {err}")))
+}
 
-    prompt_input!("\nCaptacha: ")
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn error_for(state: i32) -> ApiError {
+        ApiResponse::<serde_json::Value>::parse(json!({ "state": state, "msg": "", "data": null }))
+            .unwrap()
+            .into_data()
+            .unwrap_err()
+    }
+
+    #[test]
+    fn session_states_need_login() {
+        assert!(matches!(
+            error_for(state::SESSION_EXPIRED),
+            ApiError::SessionExpired(_)
+        ));
+        assert!(matches!(
+            error_for(state::PERMISSION_DENIED),
+            ApiError::PermissionDenied(_)
+        ));
+        assert!(error_for(state::SESSION_EXPIRED).needs_login());
+    }
+
+    #[test]
+    fn other_states_are_unknown() {
+        for state in [500, 502, 1010076, 42] {
+            let err = error_for(state);
+            assert!(matches!(err, ApiError::Unknown { .. }), "{state}");
+            assert!(!err.needs_login(), "{state}");
+        }
+        assert!(matches!(error_for(state::NOT_FOUND), ApiError::NotFound(_)));
+    }
+
+    #[test]
+    fn ok_state_returns_data() {
+        let data: Option<u64> = ApiResponse::parse(json!({ "state": 0, "data": 3 }))
+            .unwrap()
+            .into_data()
+            .unwrap();
+        assert_eq!(data, Some(3));
+    }
 }
@@ -1,8 +1,20 @@
-use log::info;
-use serde::{Deserialize, Serialize};
-use std::time::SystemTime;
+use chrono_tz::Tz;
+use log::{info, warn};
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::{Duration, SystemTime},
+};
 
-use crate::prompt_input;
+use crate::{
+    client::DEFAULT_BASE_URL,
+    error::Error,
+    gpa::{
+        default_score_mapping_ids, default_score_mapping_lists, ScoreMappingConfig, ScoreMappingId,
+    },
+    prompt_input,
+};
 
 #[derive(Deserialize, Serialize, Default)]
 pub struct Login {
@@ -11,19 +23,19 @@ pub struct Login {
     pub timestamp: u64,
 }
 
-pub fn login() -> Login {
+pub fn login() -> Result<Login, Error> {
     let name = prompt_input!("Username: ");
-    let password = rpassword::prompt_password("Password: ").unwrap();
+    let password = rpassword::prompt_password("Password: ")?;
     let timestamp = SystemTime::now()
         .duration_since(SystemTime::UNIX_EPOCH)
         .unwrap()
         .as_secs();
     let hashed_password = get_hashed_password(password, timestamp);
-    Login {
+    Ok(Login {
         name,
         password: hashed_password,
         timestamp,
-    }
+    })
 }
 
 fn get_hashed_password(password: String, timestamp: u64) -> String {
@@ -34,25 +46,43 @@ fn get_hashed_password(password: String, timestamp: u64) -> String {
     combined_hash
 }
 
-pub fn save_login(config: &Login) {
-    confy::store("tls-xb", "login", config).expect("Failed to save login");
+pub fn save_login(config: &Login) -> Result<(), Error> {
+    Ok(confy::store("tls-xb", "login", config)?)
 }
 
-pub fn get_login() -> Login {
+pub fn get_login() -> Result<Login, Error> {
     info!(
         "Getting login.toml from {}",
-        confy::get_configuration_file_path("tls-xb", "login")
-            .unwrap()
-            .to_str()
-            .unwrap()
+        confy::get_configuration_file_path("tls-xb", "login")?.display()
     );
-    confy::load("tls-xb", "login").expect("Failed to get login")
+    Ok(confy::load("tls-xb", "login")?)
 }
 
-#[derive(Deserialize, Serialize, Default)]
+#[derive(Deserialize, Serialize)]
 #[serde(default)]
 pub struct Config {
+    pub base_url: String,
+    /// Snapshot every report into the grade history
+    pub history: bool,
     pub colors: ColorScheme,
+    pub hooks: Hooks,
+    pub rules: Rules,
+    pub cache: CacheConfig,
+    pub time_zone: TimeZoneConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            base_url: DEFAULT_BASE_URL.to_string(),
+            history: true,
+            colors: ColorScheme::default(),
+            hooks: Hooks::default(),
+            rules: Rules::default(),
+            cache: CacheConfig::default(),
+            time_zone: TimeZoneConfig::default(),
+        }
+    }
 }
 
 #[derive(Deserialize, Serialize)]
@@ -79,17 +109,417 @@ impl Default for ColorScheme {
     }
 }
 
-pub fn get_config() -> Config {
+/// Run by `tls-xb watch` when scores change, with the changes as JSON.
+#[derive(Deserialize, Serialize, Default, Clone)]
+#[serde(default)]
+pub struct Hooks {
+    /// Shell command, the changes are passed on stdin
+    pub command: Option<String>,
+    /// URL the changes are POSTed to
+    pub webhook: Option<String>,
+}
+
+/// The on-disk cache of server responses.
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct CacheConfig {
+    pub enabled: bool,
+    /// How long responses stay fresh, by endpoint prefix. The longest
+    /// matching prefix wins, endpoints that match none aren't cached.
+    pub ttl: BTreeMap<String, Ttl>,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        let day = 24 * 60 * 60;
+        let ttl = [
+            ("School/GetSchoolSemesters", 7 * day),
+            ("LearningTask/GetStuSubjectListForSelect", day),
+            ("LearningTask/GetList", day),
+            ("LearningTask/GetDetail", 7 * day),
+            ("Schedule/ListScheduleByParent", day),
+            ("DynamicScore/", 10 * 60),
+        ];
+        CacheConfig {
+            enabled: true,
+            ttl: ttl
+                .into_iter()
+                .map(|(endpoint, secs)| (endpoint.to_string(), Ttl(Duration::from_secs(secs))))
+                .collect(),
+        }
+    }
+}
+
+impl CacheConfig {
+    /// How long responses of `path` stay fresh, if they are cached at all.
+    pub fn ttl(&self, path: &str) -> Option<Duration> {
+        self.ttl
+            .iter()
+            .filter(|(endpoint, _)| path.starts_with(endpoint.as_str()))
+            .max_by_key(|(endpoint, _)| endpoint.len())
+            .map(|(_, ttl)| ttl.0)
+            .filter(|ttl| !ttl.is_zero())
+    }
+}
+
+/// A duration in the config, written like `10m` or `7days`.
+#[derive(Clone, Copy)]
+pub struct Ttl(pub Duration);
+
+impl<'de> Deserialize<'de> for Ttl {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let ttl = String::deserialize(deserializer)?;
+        humantime::parse_duration(&ttl)
+            .map(Ttl)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl Serialize for Ttl {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&humantime::format_duration(self.0).to_string())
+    }
+}
+
+/// The school's time zone, which the server's times are in.
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct TimeZoneConfig {
+    /// IANA name, also the `TZID` of exported calendars
+    pub name: String,
+}
+
+impl TimeZoneConfig {
+    /// The zone `name` refers to. Only the commands that show or export
+    /// class times look it up, so a wrong name doesn't break the others.
+    pub fn tz(&self) -> Result<Tz, Error> {
+        self.name.parse().map_err(|_| {
+            Error::config(format!(
+                "time_zone.name {} isn't an IANA time zone such as Asia/Shanghai",
+                self.name
+            ))
+        })
+    }
+}
+
+impl Default for TimeZoneConfig {
+    fn default() -> Self {
+        TimeZoneConfig {
+            name: "Asia/Shanghai".to_string(),
+        }
+    }
+}
+
+/// A regular expression in the config, checked when the config is loaded.
+#[derive(Clone)]
+pub struct Pattern(Regex);
+
+impl Pattern {
+    fn new(pattern: &str) -> Pattern {
+        Pattern(Regex::new(pattern).unwrap())
+    }
+
+    pub fn is_match(&self, haystack: &str) -> bool {
+        self.0.is_match(haystack)
+    }
+}
+
+impl std::str::FromStr for Pattern {
+    type Err = regex::Error;
+
+    fn from_str(pattern: &str) -> Result<Self, Self::Err> {
+        Regex::new(pattern).map(Pattern)
+    }
+}
+
+impl std::fmt::Display for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        Regex::new(&pattern)
+            .map(Pattern)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl Serialize for Pattern {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0.as_str())
+    }
+}
+
+/// The rules that decide how subjects count towards the GPA.
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct Rules {
+    /// Subject name patterns and the score mapping list they use, used when
+    /// the server's score mapping id is unknown. The first match wins,
+    /// subjects that match none are non-weighted.
+    #[serde(rename = "score_mapping")]
+    pub score_mapping_rules: Vec<ScoreMappingRule>,
+    /// Server score mapping ids and the list they use
+    pub score_mapping_ids: HashMap<String, ScoreMappingId>,
+    /// Custom score mapping lists, or replacements for `weighted` and `non-weighted`
+    pub score_mappings: HashMap<String, Vec<ScoreMappingConfig>>,
+    /// Subject name patterns and their weight (credits), the first match wins
+    #[serde(rename = "weight")]
+    pub weight_rules: Vec<WeightRule>,
+    /// Pattern matching the class names of electives in the schedule
+    pub elective_pattern: Pattern,
+    pub elective_weight: f64,
+    /// Subject name patterns counted in the GPA even if the server leaves them out
+    pub include_in_gpa: Vec<Pattern>,
+    /// Subject name patterns left out of the GPA
+    pub exclude_from_gpa: Vec<Pattern>,
+    /// `--include` and `--exclude`, which take precedence over the config
+    #[serde(skip)]
+    pub gpa_overrides: Vec<GpaOverride>,
+}
+
+#[derive(Clone)]
+pub struct GpaOverride {
+    pub pattern: Pattern,
+    pub in_gpa: bool,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct ScoreMappingRule {
+    pub pattern: Pattern,
+    pub score_mapping: ScoreMappingId,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct WeightRule {
+    pub pattern: Pattern,
+    pub weight: f64,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules {
+            score_mapping_rules: vec![
+                // AP, AS or A Level as whole words, split on anything but
+                // letters and digits, so "A-Level" counts and "ASL" doesn't
+                ScoreMappingRule {
+                    pattern: Pattern::new(
+                        r"(^|[^\p{Alphabetic}\p{N}])(AP|AS|A[^\p{Alphabetic}\p{N}]Level)([^\p{Alphabetic}\p{N}]|$)",
+                    ),
+                    score_mapping: ScoreMappingId::Weighted,
+                },
+                ScoreMappingRule {
+                    pattern: Pattern::new(
+                        "^(Linear Algebra|Modern Physics and Optics|Multivariable Calculus)$",
+                    ),
+                    score_mapping: ScoreMappingId::Weighted,
+                },
+            ],
+            score_mapping_ids: HashMap::new(),
+            score_mappings: HashMap::new(),
+            weight_rules: vec![WeightRule {
+                pattern: Pattern::new("^C-Humanities$"),
+                weight: 0.5,
+            }],
+            elective_pattern: Pattern::new("Ele"),
+            elective_weight: 0.5,
+            include_in_gpa: Vec::new(),
+            exclude_from_gpa: Vec::new(),
+            gpa_overrides: Vec::new(),
+        }
+    }
+}
+
+impl Rules {
+    /// The score mapping list a subject uses going by its name.
+    pub fn score_mapping(&self, subject_name: &str) -> ScoreMappingId {
+        self.score_mapping_rules
+            .iter()
+            .find(|rule| rule.pattern.is_match(subject_name))
+            .map_or(ScoreMappingId::NonWeighted, |rule| {
+                rule.score_mapping.clone()
+            })
+    }
+
+    /// The weight of a subject, if a rule sets it.
+    pub fn weight(&self, subject_name: &str) -> Option<f64> {
+        self.weight_rules
+            .iter()
+            .find(|rule| rule.pattern.is_match(subject_name))
+            .map(|rule| rule.weight)
+    }
+
+    /// Whether a subject is forced into or out of the GPA, and why.
+    /// Overrides from the command line come first, then exclusions, then
+    /// inclusions from the config.
+    pub fn gpa_override(&self, subject_name: &str) -> Option<(bool, String)> {
+        if let Some(gpa_override) = self
+            .gpa_overrides
+            .iter()
+            .find(|gpa_override| gpa_override.pattern.is_match(subject_name))
+        {
+            let flag = if gpa_override.in_gpa {
+                "--include"
+            } else {
+                "--exclude"
+            };
+            return Some((
+                gpa_override.in_gpa,
+                format!("{flag} {}", gpa_override.pattern),
+            ));
+        }
+        if let Some(pattern) = self
+            .exclude_from_gpa
+            .iter()
+            .find(|pattern| pattern.is_match(subject_name))
+        {
+            return Some((false, format!("rules.exclude_from_gpa {pattern}")));
+        }
+        self.include_in_gpa
+            .iter()
+            .find(|pattern| pattern.is_match(subject_name))
+            .map(|pattern| (true, format!("rules.include_in_gpa {pattern}")))
+    }
+
+    /// The bundled score mapping lists with the custom ones added.
+    pub fn score_mapping_lists(
+        &self,
+    ) -> Result<HashMap<ScoreMappingId, Vec<ScoreMappingConfig>>, Error> {
+        let mut score_mapping_lists = default_score_mapping_lists();
+        for (name, score_mapping_list) in &self.score_mappings {
+            score_mapping_lists.insert(
+                ScoreMappingId::from(name.clone()),
+                score_mapping_list.clone(),
+            );
+        }
+        let used = self
+            .score_mapping_rules
+            .iter()
+            .map(|rule| &rule.score_mapping)
+            .chain(self.score_mapping_ids.values());
+        for score_mapping_list_id in used {
+            if !score_mapping_lists.contains_key(score_mapping_list_id) {
+                return Err(Error::input(format!(
+                    "the rules in config.toml use the score mapping {}, which isn't defined in rules.score_mappings",
+                    String::from(score_mapping_list_id.clone())
+                )));
+            }
+        }
+        Ok(score_mapping_lists)
+    }
+
+    /// The bundled score mapping ids, with the ones from the rules added or
+    /// replacing them.
+    pub fn score_mapping_ids(&self) -> HashMap<u64, ScoreMappingId> {
+        let mut score_mapping_ids = default_score_mapping_ids();
+        for (id, score_mapping_list_id) in &self.score_mapping_ids {
+            match id.parse() {
+                Ok(id) => {
+                    score_mapping_ids.insert(id, score_mapping_list_id.clone());
+                }
+                Err(_) => warn!("Ignoring rules.score_mapping_ids.{id}, ids are numbers"),
+            }
+        }
+        score_mapping_ids
+    }
+}
+
+pub fn get_config() -> Result<Config, Error> {
     info!(
         "Getting config.toml from {}",
-        confy::get_configuration_file_path("tls-xb", "config")
-            .unwrap()
-            .to_str()
-            .unwrap()
+        confy::get_configuration_file_path("tls-xb", "config")?.display()
     );
-    confy::load("tls-xb", "config").expect("Failed to get config")
+    Ok(confy::load("tls-xb", "config")?)
 }
 
-pub fn save_config(config: &Config) {
-    confy::store("tls-xb", "config", config).expect("Failed to save config");
+pub fn save_config(config: &Config) -> Result<(), Error> {
+    Ok(confy::store("tls-xb", "config", config)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_score_mapping_rules() {
+        let rules = Rules::default();
+        for weighted in [
+            "AP Calculus BC",
+            "AP-Calculus",
+            "AP_Calculus",
+            "AS Chemistry",
+            "A Level Physics",
+            "A-Level Physics",
+            "Physics (A Level)",
+            "Further Maths, AS",
+            "Linear Algebra",
+            "Multivariable Calculus",
+        ] {
+            assert!(
+                rules.score_mapping(weighted) == ScoreMappingId::Weighted,
+                "{weighted}"
+            );
+        }
+        for non_weighted in [
+            "ASL",
+            "Class Meeting",
+            "APCalculus",
+            "ap calculus",
+            "A--Level Physics",
+            "Level A",
+            "Pre-AP2",
+            "Linear Algebra II",
+            "English",
+        ] {
+            assert!(
+                rules.score_mapping(non_weighted) == ScoreMappingId::NonWeighted,
+                "{non_weighted}"
+            );
+        }
+    }
+
+    #[test]
+    fn time_zones_are_looked_up_by_name() {
+        let time_zone = |name: &str| TimeZoneConfig {
+            name: name.to_string(),
+        };
+        assert_eq!(TimeZoneConfig::default().tz().unwrap(), Tz::Asia__Shanghai);
+        assert_eq!(
+            time_zone("America/New_York").tz().unwrap(),
+            Tz::America__New_York
+        );
+        let err = time_zone("Asia/Beijing").tz().unwrap_err();
+        assert_eq!(err.exit_code(), 3);
+        // Only the time zone section, an old utc_offset is ignored
+        let config: Config =
+            toml::from_str("[time_zone]\nname = \"Europe/London\"\nutc_offset = \"+00:00\"")
+                .unwrap();
+        assert_eq!(config.time_zone.tz().unwrap(), Tz::Europe__London);
+    }
+
+    #[test]
+    fn configured_score_mapping_ids_replace_bundled_ones() {
+        let mut rules = Rules::default();
+        rules
+            .score_mapping_ids
+            .insert("2".to_string(), ScoreMappingId::Weighted);
+        rules
+            .score_mapping_ids
+            .insert("123".to_string(), ScoreMappingId::Weighted);
+        let ids = rules.score_mapping_ids();
+        assert!(ids[&1] == ScoreMappingId::Weighted);
+        assert!(ids[&2] == ScoreMappingId::Weighted);
+        assert!(ids[&123] == ScoreMappingId::Weighted);
+    }
+
+    #[test]
+    fn default_weight_rules() {
+        let rules = Rules::default();
+        assert_eq!(rules.weight("C-Humanities"), Some(0.5));
+        assert_eq!(rules.weight("C-Humanities 2"), None);
+        assert!(rules.elective_pattern.is_match("Ele-Art"));
+    }
 }
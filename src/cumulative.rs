@@ -0,0 +1,225 @@
+use crate::{
+    client::SchoolisApi,
+    config::Rules,
+    error::Error,
+    fetch_subjects,
+    gpa::{calculate_gpa, get_gpa, ScoreMappingConfig, ScoreMappingId},
+    report::{CalculatedGpaReport, SemesterReport, SCHEMA_VERSION},
+    semester::Semester,
+    subject::Subject,
+};
+use chrono::{DateTime, Utc};
+use colored::Colorize;
+use futures::future::try_join_all;
+use itertools::Itertools;
+use log::info;
+use serde::Serialize;
+use std::{collections::HashMap, io::Write, sync::Arc};
+use tabled::{
+    settings::{object::Rows, Remove, Style},
+    Table,
+};
+
+/// GPA of every semester, academic year and all of them together,
+/// as emitted by `tls-xb cumulative --format json`.
+///
+/// Year and cumulative GPAs are weighted by subject weight, the same way
+/// as the GPA of a single semester.
+#[derive(Serialize)]
+pub struct CumulativeReport {
+    pub schema_version: u32,
+    pub generated_at: DateTime<Utc>,
+    pub semesters: Vec<SemesterGpaReport>,
+    pub years: Vec<YearGpaReport>,
+    pub cumulative: CalculatedGpaReport,
+}
+
+#[derive(Serialize)]
+pub struct SemesterGpaReport {
+    pub semester: SemesterReport,
+    /// Official GPA from the server
+    pub gpa: Option<f64>,
+    pub calculated_gpa: CalculatedGpaReport,
+}
+
+#[derive(Serialize)]
+pub struct YearGpaReport {
+    /// Academic year, e.g. `2024-2025`
+    pub year: String,
+    pub calculated_gpa: CalculatedGpaReport,
+}
+
+/// Fetches every semester that has started, concurrently.
+pub async fn fetch(
+    client: &Arc<dyn SchoolisApi>,
+    semesters: &[Semester],
+    score_mapping_lists: &Arc<HashMap<ScoreMappingId, Vec<ScoreMappingConfig>>>,
+    rules: &Rules,
+) -> Result<CumulativeReport, Error> {
+    let now = Utc::now();
+    let semesters: Vec<&Semester> = semesters
+        .iter()
+        .filter(|semester| semester.start_date.date() <= now.date_naive())
+        .sorted_by_key(|semester| semester.start_date)
+        .collect();
+    info!("Fetching {} semesters", semesters.len());
+    let results = try_join_all(semesters.iter().map(|semester| async move {
+        let subjects = fetch_subjects(client, semester.id, score_mapping_lists, rules).await?;
+        let gpa = get_gpa(&**client, semester.id).await?;
+        Ok::<(Vec<Subject>, f64), Error>((subjects, gpa))
+    }))
+    .await?;
+
+    let mut years = Vec::new();
+    for (year, semesters) in &semesters
+        .iter()
+        .zip(&results)
+        .chunk_by(|(semester, _)| semester.academic_year())
+    {
+        let subjects: Vec<Subject> = semesters
+            .flat_map(|(_, (subjects, _))| subjects.iter().cloned())
+            .collect();
+        years.push(YearGpaReport {
+            year,
+            calculated_gpa: CalculatedGpaReport::from(&calculate_gpa(&subjects)),
+        });
+    }
+    let all_subjects: Vec<Subject> = results
+        .iter()
+        .flat_map(|(subjects, _)| subjects.iter().cloned())
+        .collect();
+    Ok(CumulativeReport {
+        schema_version: SCHEMA_VERSION,
+        generated_at: now,
+        semesters: semesters
+            .iter()
+            .zip(&results)
+            .map(|(semester, (subjects, gpa))| SemesterGpaReport {
+                semester: SemesterReport::from(*semester),
+                gpa: gpa.is_finite().then_some(*gpa),
+                calculated_gpa: CalculatedGpaReport::from(&calculate_gpa(subjects)),
+            })
+            .collect(),
+        years,
+        cumulative: CalculatedGpaReport::from(&calculate_gpa(&all_subjects)),
+    })
+}
+
+fn format_gpa(gpa: Option<f64>, max_gpa: Option<f64>) -> String {
+    match (gpa, max_gpa) {
+        (Some(gpa), Some(max_gpa)) => format!("{gpa:.2} / {max_gpa:.2}"),
+        (Some(gpa), None) => format!("{gpa:.2}"),
+        _ => "-".to_string(),
+    }
+}
+
+/// One row of the table and of `--format csv`.
+#[derive(Serialize)]
+struct GpaRow {
+    /// `semester`, `year` or `cumulative`
+    level: &'static str,
+    name: String,
+    semester_id: Option<u64>,
+    gpa: Option<f64>,
+    weighted_gpa: Option<f64>,
+    max_gpa: Option<f64>,
+    unweighted_gpa: Option<f64>,
+    unweighted_max_gpa: Option<f64>,
+}
+
+impl GpaRow {
+    fn new(
+        level: &'static str,
+        name: String,
+        semester_id: Option<u64>,
+        gpa: Option<f64>,
+        calculated_gpa: &CalculatedGpaReport,
+    ) -> GpaRow {
+        GpaRow {
+            level,
+            name,
+            semester_id,
+            gpa,
+            weighted_gpa: calculated_gpa.weighted_gpa,
+            max_gpa: calculated_gpa.max_gpa,
+            unweighted_gpa: calculated_gpa.unweighted_gpa,
+            unweighted_max_gpa: calculated_gpa.unweighted_max_gpa,
+        }
+    }
+}
+
+impl CumulativeReport {
+    /// Semesters grouped by academic year, each year followed by its GPA,
+    /// and the cumulative GPA last.
+    fn rows(&self) -> Vec<GpaRow> {
+        let mut rows = Vec::new();
+        for year in &self.years {
+            for semester_gpa in &self.semesters {
+                let semester = &semester_gpa.semester;
+                if semester.academic_year() != year.year {
+                    continue;
+                }
+                rows.push(GpaRow::new(
+                    "semester",
+                    format!("{}, Semester {}", year.year, semester.semester),
+                    Some(semester.id),
+                    semester_gpa.gpa,
+                    &semester_gpa.calculated_gpa,
+                ));
+            }
+            rows.push(GpaRow::new(
+                "year",
+                year.year.clone(),
+                None,
+                None,
+                &year.calculated_gpa,
+            ));
+        }
+        rows.push(GpaRow::new(
+            "cumulative",
+            "Cumulative".to_string(),
+            None,
+            None,
+            &self.cumulative,
+        ));
+        rows
+    }
+
+    pub fn print(&self) {
+        let mut data = vec![(
+            String::new(),
+            "GPA".to_string(),
+            "Calculated GPA".to_string(),
+            "Unweighted GPA".to_string(),
+        )];
+        for row in self.rows() {
+            let name = if row.level == "semester" {
+                row.name
+            } else {
+                row.name.bold().to_string()
+            };
+            data.push((
+                name,
+                row.gpa.map_or("-".to_string(), |gpa| gpa.to_string()),
+                format_gpa(row.weighted_gpa, row.max_gpa),
+                format_gpa(row.unweighted_gpa, row.unweighted_max_gpa),
+            ));
+        }
+        let table = Table::new(data)
+            .with(Remove::row(Rows::first()))
+            .with(Style::rounded())
+            .to_string();
+        println!("{table}");
+    }
+
+    pub fn write_delimited(&self, writer: impl Write, delimiter: u8) -> Result<(), Error> {
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(delimiter)
+            .from_writer(writer);
+        for row in self.rows() {
+            writer.serialize(row)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,153 @@
+use crate::client::ApiError;
+use std::backtrace::Backtrace;
+
+/// Every error tls-xb reports to the user. Each class of error exits
+/// with its own code, see [`Error::exit_code`].
+///
+/// The backtrace is only captured when `RUST_LIB_BACKTRACE` is set,
+/// which `-v` does. It is boxed to keep `Result<_, Error>` small.
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("invalid input: {0}")]
+    Input(String, Box<Backtrace>),
+    #[error("config error: {0}")]
+    Config(#[source] confy::ConfyError, Box<Backtrace>),
+    /// A setting in config.toml that reads fine but can't be used
+    #[error("invalid config: {0}")]
+    InvalidConfig(String, Box<Backtrace>),
+    #[error("login failed: {0}")]
+    Login(String, Box<Backtrace>),
+    #[error("network error: {0}")]
+    Network(#[source] reqwest::Error, Box<Backtrace>),
+    /// A response that isn't cached under `--offline`, or isn't in the
+    /// `--replay` recording, exits like a network error
+    #[error("{0}")]
+    Unavailable(String, Box<Backtrace>),
+    #[error("server error: {0}")]
+    Api(#[source] ApiError, Box<Backtrace>),
+    #[error("failed to parse response: {0}")]
+    Parse(#[source] serde_json::Error, Box<Backtrace>),
+    #[error("{0}")]
+    Io(#[source] std::io::Error, Box<Backtrace>),
+}
+
+fn capture() -> Box<Backtrace> {
+    Box::new(Backtrace::capture())
+}
+
+impl From<ApiError> for Error {
+    fn from(err: ApiError) -> Self {
+        match err {
+            ApiError::Network(err) => Error::Network(err, capture()),
+            ApiError::Parse(err) => Error::Parse(err, capture()),
+            ApiError::NotCached(path) => Error::Unavailable(
+                format!("{path} is not cached, run once without --offline first"),
+                capture(),
+            ),
+            ApiError::NotRecorded(path) => Error::Unavailable(
+                format!("{path} is not in the replayed recording"),
+                capture(),
+            ),
+            ApiError::Relogin(err) => *err,
+            err => Error::Api(err, capture()),
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(err: reqwest::Error) -> Self {
+        Error::Network(err, capture())
+    }
+}
+
+impl From<confy::ConfyError> for Error {
+    fn from(err: confy::ConfyError) -> Self {
+        Error::Config(err, capture())
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::Parse(err, capture())
+    }
+}
+
+impl From<csv::Error> for Error {
+    fn from(err: csv::Error) -> Self {
+        Error::Io(err.into(), capture())
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Io(err, capture())
+    }
+}
+
+impl Error {
+    pub fn input(msg: impl Into<String>) -> Error {
+        Error::Input(msg.into(), capture())
+    }
+
+    pub fn config(msg: impl Into<String>) -> Error {
+        Error::InvalidConfig(msg.into(), capture())
+    }
+
+    pub fn login(msg: impl Into<String>) -> Error {
+        Error::Login(msg.into(), capture())
+    }
+
+    /// Exit codes, as documented in the README:
+    ///
+    /// | Code | Error                                    |
+    /// | ---- | ---------------------------------------- |
+    /// | 1    | I/O error                                |
+    /// | 2    | Invalid input                            |
+    /// | 3    | Config error                             |
+    /// | 4    | Login failed                             |
+    /// | 5    | Network error, or not cached or recorded |
+    /// | 6    | Server error                             |
+    /// | 7    | Parse error                              |
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Io(..) => 1,
+            Error::Input(..) => 2,
+            Error::Config(..) | Error::InvalidConfig(..) => 3,
+            Error::Login(..) => 4,
+            Error::Network(..) | Error::Unavailable(..) => 5,
+            Error::Api(..) => 6,
+            Error::Parse(..) => 7,
+        }
+    }
+
+    pub fn backtrace(&self) -> &Backtrace {
+        match self {
+            Error::Input(_, backtrace)
+            | Error::Config(_, backtrace)
+            | Error::InvalidConfig(_, backtrace)
+            | Error::Login(_, backtrace)
+            | Error::Network(_, backtrace)
+            | Error::Unavailable(_, backtrace)
+            | Error::Api(_, backtrace)
+            | Error::Parse(_, backtrace)
+            | Error::Io(_, backtrace) => backtrace,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_responses_exit_like_network_errors() {
+        let not_cached = Error::from(ApiError::NotCached("LearningTask/GetList".to_string()));
+        let not_recorded = Error::from(ApiError::NotRecorded(
+            "GET School/GetSchoolSemesters".to_string(),
+        ));
+        assert_eq!(not_cached.exit_code(), 5);
+        assert_eq!(not_recorded.exit_code(), 5);
+        assert_eq!(Error::input("bad").exit_code(), 2);
+        assert_eq!(Error::config("bad").exit_code(), 3);
+    }
+}
@@ -0,0 +1,224 @@
+use crate::{
+    config::Rules,
+    gpa::{calculate_gpa, ScoreMappingConfig},
+    round_score,
+    subject::{get_subject_score, EvaluationProject, Subject},
+};
+use colored::Colorize;
+use itertools::Itertools;
+
+/// The row of the score mapping list a score falls in, after the rounding
+/// `gpa_from_score` applies.
+fn matched_row(
+    score: f64,
+    score_mapping_list: &[ScoreMappingConfig],
+) -> Option<&ScoreMappingConfig> {
+    let rounded = (score * 10.0).round() / 10.0;
+    score_mapping_list
+        .iter()
+        .find(|config| config.min_value <= rounded && config.max_value >= rounded)
+}
+
+fn format_row(config: &ScoreMappingConfig) -> String {
+    format!(
+        "{} ({} to {}) = {}",
+        config.display_name, config.min_value, config.max_value, config.gpa
+    )
+}
+
+fn explain_projects(evaluation_projects: &[EvaluationProject], indent: usize, top_level: bool) {
+    let total_proportion: f64 = evaluation_projects
+        .iter()
+        .filter(|evaluation_project| !evaluation_project.score_is_null)
+        .map(|evaluation_project| evaluation_project.proportion)
+        .sum();
+    let padding = " ".repeat(indent);
+    for evaluation_project in evaluation_projects {
+        let name = &evaluation_project.evaluation_project_e_name;
+        if evaluation_project.score_is_null {
+            println!(
+                "{padding}{name}: no score, its {}% is left out",
+                round_score(evaluation_project.proportion, 2)
+            );
+        } else {
+            // Only top-level projects add up to the subject score, the
+            // score of a project already includes its sub-projects
+            let contribution = if top_level {
+                format!(
+                    " contributes {}",
+                    round_score(
+                        evaluation_project.score * evaluation_project.adjusted_proportion / 100.0,
+                        2
+                    )
+                )
+            } else {
+                String::new()
+            };
+            println!(
+                "{padding}{name}: {}% / {}% -> {}%, score {}{contribution}",
+                round_score(evaluation_project.proportion, 2),
+                round_score(total_proportion, 2),
+                round_score(evaluation_project.adjusted_proportion, 2),
+                round_score(evaluation_project.score, 2),
+            );
+            if !evaluation_project.learning_task_and_exam_list.is_empty() {
+                match evaluation_project.task_weighting {
+                    Some(task_weighting) => {
+                        println!(
+                            "{padding}  tasks are {task_weighting}, which reproduces the score"
+                        )
+                    }
+                    None => match &evaluation_project.reproducing_task_weightings()[..] {
+                        [] => println!(
+                            "{padding}  no task weighting reproduces the score, tasks count equally"
+                        ),
+                        task_weightings => println!(
+                            "{padding}  tasks may be {}, which all reproduce the score, tasks count equally",
+                            task_weightings.iter().join(" or ")
+                        ),
+                    },
+                }
+            }
+        }
+        if !evaluation_project.evaluation_project_list.is_empty() {
+            explain_projects(
+                &evaluation_project.evaluation_project_list,
+                indent + 2,
+                false,
+            );
+        }
+    }
+}
+
+/// Prints how the score, GPA and weight of a subject were derived.
+pub fn print_subject(subject: &Subject, rules: &Rules) {
+    println!("{}", subject.subject_name.bold());
+    println!("  Proportions of the projects with a score, scaled to add up to 100%:");
+    explain_projects(&subject.evaluation_projects, 4, true);
+
+    let calculated_score = get_subject_score(&subject.evaluation_projects);
+    let contributions: Vec<String> = subject
+        .evaluation_projects
+        .iter()
+        .filter(|evaluation_project| !evaluation_project.score_is_null)
+        .map(|evaluation_project| {
+            round_score(
+                evaluation_project.score * evaluation_project.adjusted_proportion / 100.0,
+                2,
+            )
+            .to_string()
+        })
+        .collect();
+    if contributions.is_empty() {
+        println!("  Score: no project has a score");
+    } else {
+        println!(
+            "  Score: {} = {}",
+            contributions.join(" + "),
+            round_score(calculated_score, 2)
+        );
+    }
+
+    // The overlay is what sets the score mapping id
+    match subject.score_mapping_id {
+        Some(_) if subject.total_score.is_nan() => println!("  Dynamic score: unreleased"),
+        Some(_) => println!(
+            "  Dynamic score: {}, extra credit {} - {} = {}",
+            round_score(subject.total_score, 2),
+            round_score(subject.total_score, 2),
+            round_score(calculated_score, 1),
+            round_score(subject.extra_credit, 2)
+        ),
+        None => println!("  No dynamic score, the calculated score is used"),
+    }
+
+    if subject.total_score.is_nan() {
+        println!("  GPA: no score, left out of the GPA");
+    } else {
+        let rounded = round_score(subject.total_score, 1);
+        match matched_row(subject.total_score, &subject.score_mapping_list) {
+            Some(config) => println!(
+                "  GPA: {} rounds to {rounded}, {} on the {} list{}",
+                round_score(subject.total_score, 2),
+                format_row(config),
+                subject.score_mapping_list_id,
+                subject
+                    .score_mapping_id
+                    .map(|id| format!(" (score mapping {id})"))
+                    .unwrap_or_default()
+            ),
+            None => println!(
+                "  GPA: {rounded} matches no row of the {} list",
+                subject.score_mapping_list_id
+            ),
+        }
+        println!(
+            "  Unweighted GPA: {}, max GPA {} / {}",
+            subject.unweighted_gpa, subject.max_gpa, subject.unweighted_max_gpa
+        );
+    }
+
+    let weight_reason = match rules
+        .weight_rules
+        .iter()
+        .find(|rule| rule.pattern.is_match(&subject.subject_name))
+    {
+        Some(rule) => format!("the weight rule {}", rule.pattern),
+        None if subject.elective => "an elective".to_string(),
+        None => "the default".to_string(),
+    };
+    println!("  Weight: {} from {weight_reason}", subject.weight);
+    println!();
+}
+
+/// Prints how the calculated GPAs were aggregated from the subjects.
+pub fn print_gpa(subjects: &[Subject], gpa: f64) {
+    let calculated_gpa = calculate_gpa(subjects);
+    let (counted, skipped): (Vec<&Subject>, Vec<&Subject>) =
+        subjects.iter().partition(|subject| subject.counts_in_gpa());
+    let total_weight: f64 = counted.iter().map(|subject| subject.weight).sum();
+    let terms = |value: fn(&Subject) -> f64| {
+        counted
+            .iter()
+            .map(|subject| format!("{} × {}", value(subject), subject.weight))
+            .collect::<Vec<String>>()
+            .join(" + ")
+    };
+    println!("{}", "Calculated GPA".bold());
+    println!(
+        "  Weighted: ({}) / {total_weight} = {:.2}",
+        terms(|subject| subject.gpa),
+        calculated_gpa.weighted_gpa
+    );
+    println!(
+        "  Unweighted: ({}) / {total_weight} = {:.2}",
+        terms(|subject| subject.unweighted_gpa),
+        calculated_gpa.unweighted_gpa
+    );
+    println!(
+        "  Max: ({}) / {total_weight} = {:.2}",
+        terms(|subject| subject.max_gpa),
+        calculated_gpa.max_gpa
+    );
+    if !skipped.is_empty() {
+        let names: Vec<String> = skipped
+            .iter()
+            .map(|subject| {
+                format!(
+                    "{} ({})",
+                    subject.subject_name,
+                    subject.exclusion_reason().unwrap_or_default()
+                )
+            })
+            .collect();
+        println!("  Left out: {}", names.join(", "));
+    }
+    if gpa.is_nan() {
+        println!("  Official GPA: unreleased");
+    } else {
+        println!(
+            "  Official GPA: {gpa}, {:+.2} from the calculated GPA",
+            gpa - calculated_gpa.weighted_gpa
+        );
+    }
+}
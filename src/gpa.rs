@@ -1,30 +1,64 @@
-use crate::subject::{Subject, SubjectDetail};
+use crate::{
+    client::{self, ApiError, SchoolisApi},
+    subject::Subject,
+};
 use core::fmt;
-use serde::Deserialize;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
-#[derive(Eq, PartialEq, Hash, Clone)]
+/// A score mapping list, named `weighted`, `non-weighted` or after a custom
+/// list in the config.
+#[derive(Deserialize, Serialize, Eq, PartialEq, Hash, Clone)]
+#[serde(from = "String", into = "String")]
 pub enum ScoreMappingId {
     Weighted,
     NonWeighted,
+    Custom(String),
+}
+
+impl From<String> for ScoreMappingId {
+    fn from(name: String) -> Self {
+        match name.as_str() {
+            "weighted" => ScoreMappingId::Weighted,
+            "non-weighted" => ScoreMappingId::NonWeighted,
+            _ => ScoreMappingId::Custom(name),
+        }
+    }
+}
+
+impl From<ScoreMappingId> for String {
+    fn from(score_mapping_list_id: ScoreMappingId) -> Self {
+        match score_mapping_list_id {
+            ScoreMappingId::Weighted => "weighted".to_string(),
+            ScoreMappingId::NonWeighted => "non-weighted".to_string(),
+            ScoreMappingId::Custom(name) => name,
+        }
+    }
 }
 
 impl fmt::Display for ScoreMappingId {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
+        match self {
             ScoreMappingId::Weighted => write!(f, "Weighted"),
             ScoreMappingId::NonWeighted => write!(f, "Non-Weighted"),
+            ScoreMappingId::Custom(name) => write!(f, "{name}"),
         }
     }
 }
 
-#[derive(Deserialize, Clone)]
+/// Field names are camelCase in `score_mapping_configs.json`, and may be
+/// snake_case in the rules in config.toml.
+#[derive(Deserialize, Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct ScoreMappingConfig {
-    display_name: String,
-    min_value: f64,
-    max_value: f64,
-    gpa: f64,
+    #[serde(alias = "display_name")]
+    pub display_name: String,
+    #[serde(alias = "min_value")]
+    pub min_value: f64,
+    #[serde(alias = "max_value")]
+    pub max_value: f64,
+    pub gpa: f64,
 }
 
 pub fn default_score_mapping_lists() -> HashMap<ScoreMappingId, Vec<ScoreMappingConfig>> {
@@ -40,24 +74,16 @@ pub fn default_score_mapping_lists() -> HashMap<ScoreMappingId, Vec<ScoreMapping
     score_mapping_list
 }
 
-pub fn get_score_mapping_list_id(subject_detail: &SubjectDetail) -> ScoreMappingId {
-    if is_weighted_subject(&subject_detail.subject_name) {
-        return ScoreMappingId::Weighted;
-    }
-    ScoreMappingId::NonWeighted
-}
-
-const EXTRA_WEIGHTED_SUBJECT: [&str; 3] = [
-    "Linear Algebra",
-    "Modern Physics and Optics",
-    "Multivariable Calculus",
-];
-
-pub fn is_weighted_subject(subject_name: &str) -> bool {
-    subject_name.contains("AP")
-        || subject_name.contains("A Level")
-        || subject_name.contains("AS")
-        || EXTRA_WEIGHTED_SUBJECT.contains(&subject_name)
+/// Score mapping ids of the server, keyed by id, for the ids known to use
+/// one of the bundled lists.
+pub fn default_score_mapping_ids() -> HashMap<u64, ScoreMappingId> {
+    let score_mapping_configs: serde_json::Value =
+        serde_json::from_str(include_str!("score_mapping_configs.json")).unwrap();
+    let ids: HashMap<String, ScoreMappingId> =
+        serde_json::from_value(score_mapping_configs["ids"].clone()).unwrap();
+    ids.into_iter()
+        .map(|(id, score_mapping_list_id)| (id.parse().unwrap(), score_mapping_list_id))
+        .collect()
 }
 
 pub fn gpa_from_score(total_score: f64, score_mapping_list: &[ScoreMappingConfig]) -> f64 {
@@ -95,35 +121,207 @@ pub fn calculate_gpa(subjects: &[Subject]) -> CalculatedGPA {
     let mut total_weighted_gpa = 0.0;
     let mut total_max_gpa = 0.0;
     let mut total_unweighted_gpa = 0.0;
-    for subject in subjects.iter().filter(|subject| !subject.gpa.is_nan()) {
+    let mut total_unweighted_max_gpa = 0.0;
+    for subject in subjects.iter().filter(|subject| subject.counts_in_gpa()) {
         total_weight += subject.weight;
         total_weighted_gpa += subject.gpa * subject.weight;
         total_unweighted_gpa += subject.unweighted_gpa * subject.weight;
         total_max_gpa += subject.max_gpa * subject.weight;
+        total_unweighted_max_gpa += subject.unweighted_max_gpa * subject.weight;
     }
     CalculatedGPA {
         weighted_gpa: total_weighted_gpa / total_weight,
         unweighted_gpa: total_unweighted_gpa / total_weight,
         max_gpa: total_max_gpa / total_weight,
-        unweighted_max_gpa: if subjects.is_empty() {
-            f64::NAN
-        } else {
-            subjects.first().unwrap().unweighted_max_gpa
-        },
+        unweighted_max_gpa: total_unweighted_max_gpa / total_weight,
     }
 }
 
-pub async fn get_gpa(client: &reqwest::Client, semester_id: u64) -> f64 {
-    let response: serde_json::Value = client
-        .get(format!(
-            "https://tsinglanstudent.schoolis.cn/api/DynamicScore/GetGpa?semesterId={semester_id}",
-        ))
-        .send()
-        .await
-        .unwrap()
-        .json()
-        .await
-        .unwrap();
-
-    response["data"].as_f64().unwrap_or(f64::NAN)
+pub async fn get_gpa(api: &dyn SchoolisApi, semester_id: u64) -> Result<f64, ApiError> {
+    let gpa: Option<f64> = client::get(
+        api,
+        &format!("DynamicScore/GetGpa?semesterId={semester_id}"),
+    )
+    .await?;
+    Ok(gpa.unwrap_or(f64::NAN))
+}
+
+/// The list that reproduces the GPA the server gave every scored evaluation
+/// project of `subject`, if exactly one does.
+fn infer_score_mapping_list_id(
+    subject: &Subject,
+    score_mapping_lists: &HashMap<ScoreMappingId, Vec<ScoreMappingConfig>>,
+) -> Option<ScoreMappingId> {
+    let scored_projects: Vec<_> = subject
+        .evaluation_projects
+        .iter()
+        .filter(|evaluation_project| {
+            !evaluation_project.score_is_null && evaluation_project.gpa.is_finite()
+        })
+        .collect();
+    if scored_projects.is_empty() {
+        return None;
+    }
+    let mut candidates = score_mapping_lists
+        .iter()
+        .filter(|(_, score_mapping_list)| {
+            scored_projects.iter().all(|evaluation_project| {
+                let gpa = gpa_from_score(evaluation_project.score, score_mapping_list);
+                (gpa - evaluation_project.gpa).abs() < 0.01
+            })
+        });
+    match (candidates.next(), candidates.next()) {
+        (Some((score_mapping_list_id, _)), None) => Some(score_mapping_list_id.clone()),
+        _ => None,
+    }
+}
+
+/// Sets the score mapping list of every subject from the server's score
+/// mapping id, looked up in `known_ids`, the bundled ids and the ones
+/// configured in the rules. Only ids missing there are learned from the
+/// project GPAs the server calculated, subjects whose id is still unknown
+/// keep the list guessed from their name.
+pub fn resolve_score_mappings(
+    subjects: &mut [Subject],
+    known_ids: &HashMap<u64, ScoreMappingId>,
+    score_mapping_lists: &HashMap<ScoreMappingId, Vec<ScoreMappingConfig>>,
+) {
+    let mut ids = known_ids.clone();
+    let mut inferred: HashMap<u64, Option<ScoreMappingId>> = HashMap::new();
+    for subject in subjects.iter() {
+        let Some(id) = subject.score_mapping_id else {
+            continue;
+        };
+        if ids.contains_key(&id) {
+            continue;
+        }
+        let Some(score_mapping_list_id) = infer_score_mapping_list_id(subject, score_mapping_lists)
+        else {
+            continue;
+        };
+        // Subjects that disagree make the id ambiguous
+        inferred
+            .entry(id)
+            .and_modify(|inferred| {
+                if inferred.as_ref() != Some(&score_mapping_list_id) {
+                    *inferred = None;
+                }
+            })
+            .or_insert(Some(score_mapping_list_id));
+    }
+    for (id, score_mapping_list_id) in inferred {
+        if let Some(score_mapping_list_id) = score_mapping_list_id {
+            info!("Score mapping {id} looks {score_mapping_list_id}");
+            ids.insert(id, score_mapping_list_id);
+        }
+    }
+
+    for subject in subjects {
+        let Some(id) = subject.score_mapping_id else {
+            continue;
+        };
+        let Some(score_mapping_list_id) = ids.get(&id) else {
+            info!(
+                "Unknown score mapping {id} for {}, guessing {} from its name",
+                subject.subject_name, subject.score_mapping_list_id
+            );
+            continue;
+        };
+        if *score_mapping_list_id != subject.score_mapping_list_id {
+            warn!(
+                "{} uses the {score_mapping_list_id} score mapping {id}, but its name suggests {}",
+                subject.subject_name, subject.score_mapping_list_id
+            );
+            subject.set_score_mapping(score_mapping_list_id.clone(), score_mapping_lists);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::subject::EvaluationProject;
+
+    /// A subject with score mapping `id` and one project the server scored
+    /// `score` with `gpa`, or without a GPA if `None`.
+    fn subject(name: &str, id: u64, score: f64, gpa: Option<f64>) -> Subject {
+        let mut subject = Subject::for_tests(
+            name,
+            vec![
+                EvaluationProject::for_tests("Summative", 100.0, Some(score))
+                    .with_gpa(gpa.unwrap_or(f64::NAN)),
+            ],
+        );
+        subject.score_mapping_id = Some(id);
+        subject
+    }
+
+    fn resolve(subjects: &mut [Subject], known_ids: &[(u64, ScoreMappingId)]) {
+        resolve_score_mappings(
+            subjects,
+            &known_ids.iter().cloned().collect(),
+            &default_score_mapping_lists(),
+        );
+    }
+
+    #[test]
+    fn configured_id_sets_the_list() {
+        // The project GPA suggests non-weighted, but the configured id wins
+        let mut subjects = [subject("AP Physics", 7, 91.0, Some(3.7))];
+        resolve(&mut subjects, &[(7, ScoreMappingId::Weighted)]);
+        assert!(subjects[0].score_mapping_list_id == ScoreMappingId::Weighted);
+        assert_eq!(subjects[0].gpa, 4.2);
+    }
+
+    #[test]
+    fn bundled_id_is_looked_up_before_inferring() {
+        let known_ids = default_score_mapping_ids();
+        assert!(known_ids[&1] == ScoreMappingId::Weighted);
+        assert!(known_ids[&2] == ScoreMappingId::NonWeighted);
+        // The project GPA suggests weighted, but the bundled id wins
+        let mut subjects = [subject("AP Physics", 2, 91.0, Some(4.2))];
+        resolve_score_mappings(&mut subjects, &known_ids, &default_score_mapping_lists());
+        assert!(subjects[0].score_mapping_list_id == ScoreMappingId::NonWeighted);
+        assert_eq!(subjects[0].gpa, 3.7);
+    }
+
+    #[test]
+    fn unknown_id_is_inferred_from_project_gpas() {
+        let mut subjects = [
+            subject("AP Physics", 7, 91.0, Some(4.2)),
+            // No project GPA to learn from, but it shares the id
+            subject("AP Chemistry", 7, 88.0, None),
+        ];
+        resolve(&mut subjects, &[]);
+        for subject in &subjects {
+            assert!(subject.score_mapping_list_id == ScoreMappingId::Weighted);
+        }
+        assert_eq!(subjects[1].gpa, 3.8);
+    }
+
+    #[test]
+    fn unknown_id_without_evidence_keeps_the_name_guess() {
+        let mut subjects = [subject("Physics", 7, 91.0, None)];
+        resolve(&mut subjects, &[(8, ScoreMappingId::Weighted)]);
+        assert!(subjects[0].score_mapping_list_id == ScoreMappingId::NonWeighted);
+        assert_eq!(subjects[0].gpa, 3.7);
+    }
+
+    #[test]
+    fn ambiguous_inference_keeps_the_name_guess() {
+        // An F is 0 on both lists
+        let mut failing = [subject("Physics", 7, 50.0, Some(0.0))];
+        resolve(&mut failing, &[]);
+        assert!(failing[0].score_mapping_list_id == ScoreMappingId::NonWeighted);
+
+        // Subjects sharing the id disagree
+        let mut disagreeing = [
+            subject("Physics", 7, 91.0, Some(4.2)),
+            subject("Chemistry", 7, 91.0, Some(3.7)),
+        ];
+        resolve(&mut disagreeing, &[]);
+        for subject in &disagreeing {
+            assert!(subject.score_mapping_list_id == ScoreMappingId::NonWeighted);
+        }
+    }
 }
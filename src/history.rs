@@ -0,0 +1,443 @@
+use crate::{
+    colorize,
+    config::Config,
+    error::Error,
+    private,
+    report::{EvaluationProjectReport, GradeReport, SubjectReport},
+    round_score,
+};
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use colored::Colorize;
+use directories::ProjectDirs;
+use log::{info, warn};
+use serde::Serialize;
+use std::{collections::HashMap, fs, path::PathBuf};
+
+/// Past grade reports, stored as one JSON snapshot per run in
+/// `<data dir>/tls-xb/history/<semester id>/<timestamp>.json`.
+pub struct History {
+    dir: PathBuf,
+}
+
+impl History {
+    pub fn open() -> Result<History, Error> {
+        // Same project as confy, so the data dir sits next to the config dir
+        let project_dirs = ProjectDirs::from("rs", "", "tls-xb").ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotFound, "no home directory")
+        })?;
+        Ok(History {
+            dir: project_dirs.data_dir().join("history"),
+        })
+    }
+
+    fn semester_dir(&self, semester_id: u64) -> PathBuf {
+        self.dir.join(semester_id.to_string())
+    }
+
+    /// Snapshots of a semester, newest first.
+    fn snapshots(&self, semester_id: u64) -> Result<Vec<PathBuf>, Error> {
+        let dir = self.semester_dir(semester_id);
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|extension| extension == "json")
+            })
+            .collect();
+        // Timestamps in the file names sort chronologically
+        paths.sort();
+        paths.reverse();
+        Ok(paths)
+    }
+
+    fn load(path: &PathBuf) -> Option<GradeReport> {
+        let report = fs::read_to_string(path)
+            .map_err(Error::from)
+            .and_then(|report| Ok(serde_json::from_str(&report)?));
+        match report {
+            Ok(report) => Some(report),
+            Err(err) => {
+                warn!("Skipping snapshot {}: {err}", path.display());
+                None
+            }
+        }
+    }
+
+    /// The newest snapshot that differs from `report`, taken on or before
+    /// `until` if given.
+    pub fn previous(
+        &self,
+        report: &GradeReport,
+        until: Option<NaiveDate>,
+    ) -> Result<Option<GradeReport>, Error> {
+        for path in self.snapshots(report.semester.id)? {
+            let Some(snapshot) = History::load(&path) else {
+                continue;
+            };
+            if until.is_some_and(|until| snapshot.generated_at.date_naive() > until) {
+                continue;
+            }
+            if !same_grades(&snapshot, report)? {
+                return Ok(Some(snapshot));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Stores `report` unless it is the same as the newest snapshot.
+    /// Returns whether it was stored.
+    pub fn save(&self, report: &GradeReport) -> Result<bool, Error> {
+        let latest = self
+            .snapshots(report.semester.id)?
+            .first()
+            .and_then(History::load);
+        if let Some(latest) = latest {
+            if same_grades(&latest, report)? {
+                return Ok(false);
+            }
+        }
+        // Snapshots hold grades, keep them private like the cache
+        private::create_dir_all(&self.dir)?;
+        let dir = self.semester_dir(report.semester.id);
+        private::create_dir_all(&dir)?;
+        let path = dir.join(format!(
+            "{}.json",
+            report.generated_at.format("%Y%m%dT%H%M%SZ")
+        ));
+        private::write(&path, serde_json::to_string(report)?)?;
+        info!("Snapshot saved to {}", path.display());
+        Ok(true)
+    }
+}
+
+/// Whether two reports are the same apart from when they were generated.
+fn same_grades(a: &GradeReport, b: &GradeReport) -> Result<bool, Error> {
+    let mut a = serde_json::to_value(a)?;
+    let mut b = serde_json::to_value(b)?;
+    a["generated_at"].take();
+    b["generated_at"].take();
+    Ok(a == b)
+}
+
+/// A difference between two snapshots of the same semester.
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Change {
+    NewSubject {
+        subject: String,
+    },
+    SubjectScore {
+        subject: String,
+        old_score: Option<f64>,
+        new_score: Option<f64>,
+        old_level: String,
+        new_level: String,
+    },
+    ProjectScore {
+        subject: String,
+        path: Vec<String>,
+        old_score: Option<f64>,
+        new_score: Option<f64>,
+        old_level: String,
+        new_level: String,
+    },
+    NewTask {
+        subject: String,
+        path: Vec<String>,
+        name: String,
+        score: Option<f64>,
+        total_score: f64,
+    },
+    TaskScore {
+        subject: String,
+        path: Vec<String>,
+        name: String,
+        old_score: Option<f64>,
+        new_score: Option<f64>,
+        total_score: f64,
+    },
+    Gpa {
+        name: String,
+        old: Option<f64>,
+        new: Option<f64>,
+    },
+}
+
+/// Everything that changed between two snapshots.
+#[derive(Serialize)]
+pub struct Diff {
+    pub since: DateTime<Utc>,
+    pub changes: Vec<Change>,
+}
+
+impl Diff {
+    pub fn new(old: &GradeReport, new: &GradeReport) -> Diff {
+        let mut changes = Vec::new();
+        let old_subjects: HashMap<u64, &SubjectReport> = old
+            .subjects
+            .iter()
+            .map(|subject| (subject.subject_id, subject))
+            .collect();
+        for subject in &new.subjects {
+            match old_subjects.get(&subject.subject_id) {
+                Some(old_subject) => diff_subject(old_subject, subject, &mut changes),
+                None => changes.push(Change::NewSubject {
+                    subject: subject.subject_name.clone(),
+                }),
+            }
+        }
+        let gpas = [
+            ("GPA", old.gpa, new.gpa),
+            (
+                "Calculated GPA",
+                old.calculated_gpa.weighted_gpa,
+                new.calculated_gpa.weighted_gpa,
+            ),
+            (
+                "Calculated Unweighted GPA",
+                old.calculated_gpa.unweighted_gpa,
+                new.calculated_gpa.unweighted_gpa,
+            ),
+        ];
+        for (name, old, new) in gpas {
+            if !same_score(old, new) {
+                changes.push(Change::Gpa {
+                    name: name.to_string(),
+                    old,
+                    new,
+                });
+            }
+        }
+        Diff {
+            since: old.generated_at,
+            changes,
+        }
+    }
+
+    pub fn print(&self, config: &Config) {
+        for change in &self.changes {
+            println!("{}", format_change(change, config));
+        }
+    }
+}
+
+/// Scores are compared after rounding, so recalculations don't show up.
+fn same_score(a: Option<f64>, b: Option<f64>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => round_score(a, 2) == round_score(b, 2),
+        (a, b) => a.is_none() && b.is_none(),
+    }
+}
+
+fn diff_subject(old: &SubjectReport, new: &SubjectReport, changes: &mut Vec<Change>) {
+    if !same_score(old.total_score, new.total_score) || old.score_level != new.score_level {
+        changes.push(Change::SubjectScore {
+            subject: new.subject_name.clone(),
+            old_score: old.total_score,
+            new_score: new.total_score,
+            old_level: old.score_level.clone(),
+            new_level: new.score_level.clone(),
+        });
+    }
+    diff_projects(
+        &new.subject_name,
+        &[],
+        &old.evaluation_projects,
+        &new.evaluation_projects,
+        changes,
+    );
+}
+
+fn diff_projects(
+    subject: &str,
+    path: &[String],
+    old: &[EvaluationProjectReport],
+    new: &[EvaluationProjectReport],
+    changes: &mut Vec<Change>,
+) {
+    for evaluation_project in new {
+        let old_project = old
+            .iter()
+            .find(|old_project| old_project.name == evaluation_project.name);
+        let mut project_path = path.to_vec();
+        project_path.push(evaluation_project.name.clone());
+        let (old_score, old_level) = old_project
+            .map(|old_project| (old_project.score, old_project.score_level.clone()))
+            .unwrap_or_default();
+        if !same_score(old_score, evaluation_project.score)
+            || old_level != evaluation_project.score_level
+        {
+            changes.push(Change::ProjectScore {
+                subject: subject.to_string(),
+                path: project_path.clone(),
+                old_score,
+                new_score: evaluation_project.score,
+                old_level,
+                new_level: evaluation_project.score_level.clone(),
+            });
+        }
+        for learning_task in &evaluation_project.learning_tasks {
+            let old_task = old_project.and_then(|old_project| {
+                old_project
+                    .learning_tasks
+                    .iter()
+                    .find(|old_task| old_task.name == learning_task.name)
+            });
+            // A task that used to be unscored counts as newly released
+            match old_task.filter(|old_task| old_task.score.is_some()) {
+                Some(old_task) => {
+                    if !same_score(old_task.score, learning_task.score)
+                        || old_task.total_score != learning_task.total_score
+                    {
+                        changes.push(Change::TaskScore {
+                            subject: subject.to_string(),
+                            path: project_path.clone(),
+                            name: learning_task.name.clone(),
+                            old_score: old_task.score,
+                            new_score: learning_task.score,
+                            total_score: learning_task.total_score,
+                        });
+                    }
+                }
+                None => {
+                    if learning_task.score.is_some() {
+                        changes.push(Change::NewTask {
+                            subject: subject.to_string(),
+                            path: project_path.clone(),
+                            name: learning_task.name.clone(),
+                            score: learning_task.score,
+                            total_score: learning_task.total_score,
+                        });
+                    }
+                }
+            }
+        }
+        diff_projects(
+            subject,
+            &project_path,
+            old_project
+                .map(|old_project| old_project.evaluation_projects.as_slice())
+                .unwrap_or_default(),
+            &evaluation_project.evaluation_projects,
+            changes,
+        );
+    }
+}
+
+fn format_score(score: Option<f64>) -> String {
+    match score {
+        Some(score) => round_score(score, 2).to_string(),
+        None => "-".to_string(),
+    }
+}
+
+fn format_level(old_level: &str, new_level: &str, config: &Config) -> String {
+    let new = if new_level.is_empty() {
+        "-".to_string()
+    } else {
+        colorize(new_level, new_level, &config.colors)
+    };
+    if old_level == new_level {
+        format!(" ({new})")
+    } else if old_level.is_empty() {
+        format!(" (-> {new})")
+    } else {
+        format!(" ({old_level} -> {new})")
+    }
+}
+
+fn format_change(change: &Change, config: &Config) -> String {
+    match change {
+        Change::NewSubject { subject } => format!("{} {subject}", "+".green().bold()),
+        Change::SubjectScore {
+            subject,
+            old_score,
+            new_score,
+            old_level,
+            new_level,
+        } => format!(
+            "{} {}: {} -> {}{}",
+            "~".yellow().bold(),
+            subject.bold(),
+            format_score(*old_score),
+            format_score(*new_score),
+            format_level(old_level, new_level, config)
+        ),
+        Change::ProjectScore {
+            subject,
+            path,
+            old_score,
+            new_score,
+            old_level,
+            new_level,
+        } => {
+            let mark = if old_score.is_none() {
+                "+".green().bold()
+            } else {
+                "~".yellow().bold()
+            };
+            format!(
+                "{mark} {subject} / {}: {} -> {}{}",
+                path.join(" / "),
+                format_score(*old_score),
+                format_score(*new_score),
+                format_level(old_level, new_level, config)
+            )
+        }
+        Change::NewTask {
+            subject,
+            path,
+            name,
+            score,
+            total_score,
+        } => format!(
+            "{} {subject} / {} / {name}: {} / {total_score}",
+            "+".green().bold(),
+            path.join(" / "),
+            format_score(*score)
+        ),
+        Change::TaskScore {
+            subject,
+            path,
+            name,
+            old_score,
+            new_score,
+            total_score,
+        } => format!(
+            "{} {subject} / {} / {name}: {} -> {} / {total_score}",
+            "~".yellow().bold(),
+            path.join(" / "),
+            format_score(*old_score),
+            format_score(*new_score)
+        ),
+        Change::Gpa { name, old, new } => {
+            let delta = match (old, new) {
+                (Some(old), Some(new)) => format!(" ({:+.2})", new - old),
+                _ => String::new(),
+            };
+            format!(
+                "{} {name}: {} -> {}{delta}",
+                "~".yellow().bold(),
+                format_score(*old),
+                format_score(*new)
+            )
+        }
+    }
+}
+
+/// Prints the changes since the previous snapshot above the grade report.
+pub fn print_banner(diff: &Diff, config: &Config) {
+    if diff.changes.is_empty() {
+        return;
+    }
+    println!(
+        "{} ({})",
+        "New since last run".bold(),
+        diff.since.with_timezone(&Local).format("%Y-%m-%d %H:%M")
+    );
+    diff.print(config);
+    println!();
+}
@@ -1,28 +1,54 @@
+mod cache;
 mod calendar;
+mod check;
 mod client;
 mod config;
+mod cumulative;
+mod error;
+mod explain;
 mod gpa;
+mod history;
 mod macros;
+mod private;
+mod recording;
+mod redact;
+mod report;
+mod schedule;
 mod semester;
+mod session;
 mod subject;
+mod target;
+mod tui;
+mod watch;
+mod what_if;
 
-use chrono::Datelike;
-use clap::{Parser, Subcommand};
-use clap_verbosity_flag::{InfoLevel, Verbosity, WarnLevel};
-use client::LoginError;
+use ansi_str::AnsiStr;
+use cache::{CacheMode, CachedApi};
+use chrono::NaiveDate;
+use clap::{Parser, Subcommand, ValueEnum};
+use clap_verbosity_flag::{Verbosity, WarnLevel};
+use client::{ApiClient, LoginError, SchoolisApi};
 use colored::Colorize;
-use config::{Config, Login};
+use config::{Config, GpaOverride, Login, Pattern, Rules};
 use confy::get_configuration_file_path;
+use error::Error;
 use futures::future::join_all;
 use gpa::*;
-use log::{info, LevelFilter};
+use history::{Diff, History};
+use log::{info, warn, LevelFilter};
+use recording::{Recorder, Replay};
+use redact::Redactor;
+use report::GradeReport;
+use schedule::ScheduleRange;
 use semester::*;
-use std::{fs, path::PathBuf, sync::Arc};
+use session::Session;
+use std::{collections::HashMap, fs, io::IsTerminal, path::PathBuf, sync::Arc};
 use subject::*;
 use tabled::{
     settings::{object::Rows, Remove, Style},
     Table,
 };
+use what_if::{ProjectOverride, Scenario, TaskOverride};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -32,6 +58,57 @@ struct Cli {
     #[arg(short, long)]
     tasks: bool,
 
+    /// Show how every calculated score, GPA and weight was derived
+    #[arg(long)]
+    explain: bool,
+
+    /// Output format of the grade report
+    #[arg(short, long, global = true, value_enum, default_value_t = OutputFormat::Table)]
+    format: OutputFormat,
+
+    /// Base URL of the schoolis server, overrides `base_url` in config.toml
+    #[arg(long, global = true, env = "TLS_XB_BASE_URL", value_name = "URL")]
+    base_url: Option<String>,
+
+    /// Semester to show instead of asking: an index from the list like `#3`,
+    /// the semester id, `current`, or an academic year and semester like `2024-2025:1`
+    #[arg(short, long, global = true)]
+    semester: Option<SemesterSelector>,
+
+    /// Semesters to show, separated by commas: `all`, a semester,
+    /// a whole academic year like `2024-2025`, or a range like `2023-2024:2..current`
+    #[arg(long, value_delimiter = ',', conflicts_with = "semester")]
+    semesters: Option<Vec<SemesterRange>>,
+
+    /// Show the GPA of every semester, academic year and cumulative GPA,
+    /// same as `tls-xb cumulative`
+    #[arg(long)]
+    all_semesters: bool,
+
+    /// Count subjects matching this pattern in the GPA, even if the school leaves them out
+    #[arg(long, global = true, value_name = "PATTERN")]
+    include: Vec<Pattern>,
+
+    /// Leave subjects matching this pattern out of the GPA
+    #[arg(long, global = true, value_name = "PATTERN")]
+    exclude: Vec<Pattern>,
+
+    /// Only use cached responses, without connecting to the server
+    #[arg(long, global = true)]
+    offline: bool,
+
+    /// Ignore cached responses, fetching everything again
+    #[arg(long, global = true, conflicts_with = "offline")]
+    refresh: bool,
+
+    /// Write every request and response to this directory, without credentials
+    #[arg(long, global = true, value_name = "DIR", conflicts_with = "offline")]
+    record: Option<PathBuf>,
+
+    /// Serve every request from a directory written by --record instead of the server
+    #[arg(long, global = true, value_name = "DIR", conflicts_with_all = ["offline", "record"])]
+    replay: Option<PathBuf>,
+
     #[command(flatten)]
     verbosity: Verbosity<WarnLevel>,
 
@@ -39,13 +116,117 @@ struct Cli {
     command: Option<Commands>,
 }
 
+#[derive(ValueEnum, Clone, Copy, PartialEq)]
+enum OutputFormat {
+    /// Colored tables
+    Table,
+    /// A single JSON document with the full report
+    Json,
+    /// Comma-separated values, one row per subject, evaluation project and task
+    Csv,
+    /// Tab-separated values, one row per subject, evaluation project and task
+    Tsv,
+}
+
 #[derive(Subcommand)]
 enum Commands {
-    /// Log in to tsinglanstudent.schoolis.cn and store login info
+    /// Log in to schoolis and store login info
     Login,
     /// Export class schedule to iCalendar format
     #[clap(name = "ical")]
     ICal(ICalArgs),
+    /// Show how hypothetical scores would change subject scores and GPA
+    WhatIf(WhatIfArgs),
+    /// Show the scores needed on remaining work to reach each grade
+    Target(TargetArgs),
+    /// Show what changed since the previous run
+    Diff(DiffArgs),
+    /// Show the GPA of every semester, academic year and cumulative GPA
+    Cumulative,
+    /// Poll for new scores and run hooks when they change
+    Watch(WatchArgs),
+    /// Cross-check the server's scores and GPAs against recalculated ones
+    Check,
+    /// Browse subjects, projects and tasks interactively
+    Tui,
+    /// Replace names and ids in a JSON report or a recording with pseudonyms
+    Redact(RedactArgs),
+    /// Show the class timetable of a day or this week
+    Schedule(ScheduleArgs),
+    /// Show the next class and how long until it starts
+    Next(NextArgs),
+}
+
+#[derive(Parser)]
+struct ScheduleArgs {
+    /// Day to show, or this week
+    #[arg(default_value = "today", value_name = "today|tomorrow|week|YYYY-MM-DD")]
+    range: ScheduleRange,
+    /// Fix class times for high school
+    #[arg(long)]
+    high_school: bool,
+}
+
+#[derive(Parser)]
+struct NextArgs {
+    /// Fix class times for high school
+    #[arg(long)]
+    high_school: bool,
+}
+
+#[derive(Parser)]
+struct RedactArgs {
+    /// JSON report, response or recording directory, `-` for stdin
+    #[arg(value_name = "PATH")]
+    input: Option<PathBuf>,
+    /// File or directory to write to, stdout or `<PATH>-redacted` by default
+    #[arg(short, long, value_name = "PATH")]
+    output: Option<PathBuf>,
+    /// Also move scores by up to this many points
+    #[arg(long, value_name = "POINTS")]
+    noise: Option<f64>,
+}
+
+#[derive(Parser)]
+struct WatchArgs {
+    /// Time between polls, e.g. 30m or 1h
+    #[arg(long, default_value = "30m")]
+    interval: humantime::Duration,
+    /// Shell command to run with the changes as JSON on stdin,
+    /// overrides `hooks.command` in config.toml
+    #[arg(long)]
+    command: Option<String>,
+    /// URL to POST the changes to as JSON, overrides `hooks.webhook` in config.toml
+    #[arg(long, value_name = "URL")]
+    webhook: Option<String>,
+}
+
+#[derive(Parser)]
+struct DiffArgs {
+    /// Compare with the last snapshot taken on or before this date instead
+    #[arg(long, value_name = "YYYY-MM-DD")]
+    since: Option<NaiveDate>,
+}
+
+#[derive(Parser)]
+struct TargetArgs {
+    /// Also find the cheapest grades that reach this semester GPA
+    #[arg(long)]
+    gpa: Option<f64>,
+}
+
+#[derive(Parser)]
+struct WhatIfArgs {
+    /// Set the score of a task, adding the task if it doesn't exist.
+    /// Write a `/` inside a name as `\/`
+    #[arg(long, value_name = "SUBJECT/PROJECT/TASK=SCORE[/TOTAL]")]
+    task: Vec<TaskOverride>,
+    /// Set the score of an evaluation project. Write a `/` inside a name as `\/`
+    #[arg(long, value_name = "SUBJECT/PROJECT=SCORE")]
+    project: Vec<ProjectOverride>,
+    /// Read hypothetical scores from a TOML scenario file
+    #[arg(long, value_name = "FILE")]
+    scenario: Option<PathBuf>,
 }
 
 #[derive(Parser)]
@@ -56,107 +237,507 @@ struct ICalArgs {
     /// Fix class times for high school
     #[arg(long)]
     high_school: bool,
+    /// Write times in UTC instead of the school's time zone
+    #[arg(long)]
+    utc: bool,
+}
+
+fn main() {
+    let cli = Cli::parse();
+    let log_level = cli.verbosity.log_level_filter();
+    let verbose = log_level >= LevelFilter::Info;
+    if verbose {
+        // Set before the runtime spawns any threads, so errors capture a backtrace
+        std::env::set_var("RUST_LIB_BACKTRACE", "1");
+    }
+    env_logger::Builder::new().filter_level(log_level).init();
+
+    let result = tokio::runtime::Runtime::new()
+        .map_err(Error::from)
+        .and_then(|runtime| runtime.block_on(run(cli)));
+    if let Err(err) = result {
+        report_error(&err, verbose);
+        std::process::exit(err.exit_code());
+    }
+}
+
+fn report_error(err: &Error, verbose: bool) {
+    eprintln!("{} {err}", "error:".red().bold());
+    if !verbose {
+        return;
+    }
+    // The top-level message already includes the direct source
+    let mut source = std::error::Error::source(err).and_then(|source| source.source());
+    while let Some(cause) = source {
+        eprintln!("  caused by: {cause}");
+        source = cause.source();
+    }
+    eprintln!("\n{}", err.backtrace());
 }
 
-#[tokio::main]
-async fn main() {
-    let config_path = get_configuration_file_path("tls-xb", "config").unwrap();
+async fn run(cli: Cli) -> Result<(), Error> {
+    check_format(&cli)?;
+    let config_path = get_configuration_file_path("tls-xb", "config")?;
     if fs::metadata(&config_path).is_err() {
         // if the config file doesn't exist, save the default one
-        config::save_config(&Config::default());
+        config::save_config(&Config::default())?;
     }
-    let config = config::get_config();
-    let cli = Cli::parse();
-    env_logger::Builder::new()
-        .filter_level(cli.verbosity.into())
-        .init();
-    let client = Arc::new(match &cli.command {
-        Some(Commands::Login) => {
-            let mut config = config::login();
-            login(&mut config).await
+    let mut config = config::get_config()?;
+    // Command line overrides come before the ones in config.toml, exclusions first
+    let gpa_overrides = cli
+        .exclude
+        .iter()
+        .map(|pattern| (pattern, false))
+        .chain(cli.include.iter().map(|pattern| (pattern, true)));
+    config.rules.gpa_overrides = gpa_overrides
+        .map(|(pattern, in_gpa)| GpaOverride {
+            pattern: pattern.clone(),
+            in_gpa,
+        })
+        .collect();
+    if let Some(Commands::Redact(args)) = &cli.command {
+        // Works on files alone, no login needed
+        let redactor = Redactor::new(args.noise)?;
+        return redact::run(&redactor, args.input.as_deref(), args.output.as_deref());
+    }
+    let base_url = cli.base_url.as_ref().unwrap_or(&config.base_url);
+    let api_client = ApiClient::load(base_url)?;
+    if let Some(Commands::Login) = &cli.command {
+        if cli.offline || cli.replay.is_some() {
+            return Err(Error::input("can't log in with --offline or --replay"));
         }
-        _ => {
-            let login_path = get_configuration_file_path("tls-xb", "login").unwrap();
-            let mut login_info = if fs::metadata(&login_path).is_ok() {
-                config::get_login()
-            } else {
-                // if the login file doesn't exist, do tls-xb login.
-                config::login()
-            };
-
-            login(&mut login_info).await
+        let mut login_info = config::login()?;
+        login(&api_client, &mut login_info).await?;
+    }
+    let api_client = Arc::new(api_client);
+    let server: Arc<dyn SchoolisApi> = if let Some(dir) = &cli.replay {
+        Arc::new(Replay::open(dir)?)
+    } else if let Some(dir) = &cli.record {
+        Arc::new(Recorder::new(api_client.clone(), dir)?)
+    } else {
+        api_client.clone()
+    };
+    // A replay or --offline has no session to renew
+    let session: Arc<dyn SchoolisApi> = if cli.replay.is_some() || cli.offline {
+        server.clone()
+    } else {
+        Arc::new(Session::new(api_client.clone(), server.clone()))
+    };
+    let cached = |server: &Arc<dyn SchoolisApi>, mode| -> Result<Arc<dyn SchoolisApi>, Error> {
+        // A replay is already offline, and a recording has to see every request
+        if cli.replay.is_some() {
+            return Ok(server.clone());
+        }
+        let mode = if cli.record.is_some() {
+            CacheMode::Refresh
+        } else {
+            mode
+        };
+        if !config.cache.enabled {
+            if mode == CacheMode::Offline {
+                return Err(Error::input("--offline needs cache.enabled in config.toml"));
+            }
+            return Ok(server.clone());
         }
-    });
+        Ok(Arc::new(CachedApi::new(
+            server.clone(),
+            base_url,
+            &config.cache,
+            mode,
+        )?))
+    };
+    let client = cached(
+        &session,
+        if cli.offline {
+            CacheMode::Offline
+        } else if cli.refresh {
+            CacheMode::Refresh
+        } else {
+            CacheMode::Normal
+        },
+    )?;
+    // For fetches that must see the latest scores, e.g. refreshing the TUI
+    let fresh_client = if cli.offline {
+        client.clone()
+    } else {
+        cached(&session, CacheMode::Refresh)?
+    };
+
+    // The schedule doesn't depend on the semester list
+    if let Some(Commands::Schedule(schedule_args)) = &cli.command {
+        return schedule::schedule(
+            &*client,
+            config.time_zone.tz()?,
+            &schedule_args.range,
+            schedule_args.high_school,
+            cli.format == OutputFormat::Json,
+        )
+        .await;
+    }
+
+    if let Some(Commands::Next(next_args)) = &cli.command {
+        return schedule::next(
+            &*client,
+            config.time_zone.tz()?,
+            next_args.high_school,
+            cli.format == OutputFormat::Json,
+        )
+        .await;
+    }
 
     info!("Fetching semesters");
-    let semesters = get_semesters(&client).await;
+    // The session is checked by the first request that reaches the server
+    let semesters = get_semesters(&*client).await?;
+    api_client.save()?;
 
     if let Some(Commands::ICal(ical_args)) = &cli.command {
-        let semester = get_current_semester(&semesters).unwrap();
+        let tz = config.time_zone.tz()?;
+        let semester = cli
+            .semester
+            .as_ref()
+            .unwrap_or(&SemesterSelector::Current)
+            .resolve(&semesters)?;
         let calendar = calendar::Calendar::new(
-            &client,
-            semester.start_date.into(),
-            semester.end_date.into(),
+            &*client,
+            semester.start_date.date(),
+            semester.end_date.date(),
             ical_args.high_school,
         )
-        .await
-        .export_ical();
+        .await?
+        .export_ical(tz, ical_args.utc);
 
         if let Some(output_path) = &ical_args.output {
-            std::fs::write(output_path, calendar.to_string()).expect("Unable to write file");
+            std::fs::write(output_path, &calendar)?;
             info!("Calendar exported to: {}", output_path.display());
         } else {
             println!("{}", calendar);
         }
-        std::process::exit(0)
+        return Ok(());
     }
 
-    let semester = select_semester(&semesters);
+    let score_mapping_lists = Arc::new(config.rules.score_mapping_lists()?);
 
-    info!("Fetching subjects");
-    let score_mapping_lists = Arc::new(default_score_mapping_lists());
+    if let Some(Commands::Watch(watch_args)) = &cli.command {
+        if cli.offline || cli.replay.is_some() {
+            return Err(Error::input(
+                "tls-xb watch can't be used with --offline or --replay",
+            ));
+        }
+        let semester = cli
+            .semester
+            .as_ref()
+            .unwrap_or(&SemesterSelector::Current)
+            .resolve(&semesters)?;
+        let hooks = config::Hooks {
+            command: watch_args.command.clone().or(config.hooks.command.clone()),
+            webhook: watch_args.webhook.clone().or(config.hooks.webhook.clone()),
+        };
+        // Watch logs in again itself, and backs off if that doesn't help
+        let watch_client = cached(&server, CacheMode::Refresh)?;
+        return watch::watch(
+            &api_client,
+            &watch_client,
+            semester,
+            &score_mapping_lists,
+            &config,
+            &hooks,
+            watch_args.interval.into(),
+        )
+        .await;
+    }
 
-    let shared_client = Arc::clone(&client);
-    let subject_dynamic_scores_handle =
-        tokio::spawn(async move { get_subject_dynamic_scores(&shared_client, semester.id).await });
+    if let Some(Commands::Tui) = &cli.command {
+        // The semester can be switched from inside, so there's nothing to ask
+        let semester = cli
+            .semester
+            .as_ref()
+            .unwrap_or(&SemesterSelector::Current)
+            .resolve(&semesters)?;
+        return tui::run(
+            &client,
+            &fresh_client,
+            &semesters,
+            semester,
+            &score_mapping_lists,
+            &config,
+            cli.tasks,
+        )
+        .await;
+    }
 
-    let shared_client = Arc::clone(&client);
-    let elective_class_ids_handle =
-        tokio::spawn(async move { get_elective_class_ids(&shared_client).await });
+    if cli.all_semesters || matches!(cli.command, Some(Commands::Cumulative)) {
+        let semesters = match &cli.semesters {
+            Some(ranges) => select_semesters(&semesters, ranges)?,
+            None => semesters,
+        };
+        let report =
+            cumulative::fetch(&client, &semesters, &score_mapping_lists, &config.rules).await?;
+        match cli.format {
+            OutputFormat::Table => report.print(),
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+            OutputFormat::Csv => report.write_delimited(std::io::stdout().lock(), b',')?,
+            OutputFormat::Tsv => report.write_delimited(std::io::stdout().lock(), b'\t')?,
+        }
+        return Ok(());
+    }
+
+    if matches!(cli.command, None | Some(Commands::Login)) {
+        let selected = match &cli.semesters {
+            Some(ranges) => select_semesters(&semesters, ranges)?,
+            None => vec![select_semester(&semesters, cli.semester.as_ref())?],
+        };
+        return print_reports(&cli, &config, &client, &selected, &score_mapping_lists).await;
+    }
+    if cli.semesters.is_some() {
+        return Err(Error::input(
+            "--semesters only applies to the grade report and cumulative GPA",
+        ));
+    }
+
+    let semester = select_semester(&semesters, cli.semester.as_ref())?;
+    let (subjects, gpa, report) =
+        fetch_report(&client, &semester, &score_mapping_lists, &config.rules).await?;
+
+    if let Some(Commands::Check) = &cli.command {
+        let check_report = check::check(&subjects, gpa, &score_mapping_lists);
+        if cli.format == OutputFormat::Json {
+            println!("{}", serde_json::to_string_pretty(&check_report)?);
+        } else {
+            check_report.print();
+        }
+        return Ok(());
+    }
+
+    if let Some(Commands::Diff(diff_args)) = &cli.command {
+        let history = History::open()?;
+        let previous = history.previous(&report, diff_args.since)?;
+        if config.history {
+            history.save(&report)?;
+        }
+        let Some(previous) = previous else {
+            println!("No earlier snapshot to compare with");
+            return Ok(());
+        };
+        let diff = Diff::new(&previous, &report);
+        if cli.format == OutputFormat::Json {
+            println!("{}", serde_json::to_string_pretty(&diff)?);
+        } else if diff.changes.is_empty() {
+            println!("No changes since {}", previous.generated_at);
+        } else {
+            diff.print(&config);
+        }
+        return Ok(());
+    }
+
+    if let Some(Commands::WhatIf(what_if_args)) = &cli.command {
+        let mut scenario = match &what_if_args.scenario {
+            Some(path) => Scenario::load(path)?,
+            None => Scenario::default(),
+        };
+        scenario.tasks.extend(what_if_args.task.iter().cloned());
+        scenario
+            .projects
+            .extend(what_if_args.project.iter().cloned());
+        let mut simulated_subjects = subjects.clone();
+        what_if::apply(&mut simulated_subjects, &scenario, &score_mapping_lists)?;
+        what_if::print_comparison(&subjects, &simulated_subjects, &config);
+        return Ok(());
+    }
+
+    if let Some(Commands::Target(target_args)) = &cli.command {
+        target::print_requirements(&subjects, &config);
+        if let Some(target_gpa) = target_args.gpa {
+            target::print_target_gpa(&subjects, target_gpa, &config);
+        }
+        return Ok(());
+    }
+
+    Ok(())
+}
+
+/// Rejects a `--format` the command can't write, rather than printing
+/// tables to something expecting JSON or CSV.
+fn check_format(cli: &Cli) -> Result<(), Error> {
+    use OutputFormat::{Json, Table};
+    let supported: &[OutputFormat] = match &cli.command {
+        None | Some(Commands::Login | Commands::Cumulative) => return Ok(()),
+        Some(Commands::Check | Commands::Diff(_) | Commands::Schedule(_) | Commands::Next(_)) => {
+            &[Table, Json]
+        }
+        Some(_) => &[Table],
+    };
+    if supported.contains(&cli.format) {
+        return Ok(());
+    }
+    let name = |format: &OutputFormat| format.to_possible_value().unwrap().get_name().to_string();
+    Err(Error::input(format!(
+        "this command doesn't support --format {}, only {}",
+        name(&cli.format),
+        supported.iter().map(name).collect::<Vec<_>>().join(" and ")
+    )))
+}
 
+/// Fetches a semester's subjects and official GPA.
+async fn fetch_report(
+    client: &Arc<dyn SchoolisApi>,
+    semester: &Semester,
+    score_mapping_lists: &Arc<HashMap<ScoreMappingId, Vec<ScoreMappingConfig>>>,
+    rules: &Rules,
+) -> Result<(Vec<Subject>, f64, GradeReport), Error> {
     info!("Fetching GPA");
-    let shared_client = Arc::clone(&client);
-    let gpa_handle = tokio::spawn(async move { get_gpa(&shared_client, semester.id).await });
+    let shared_client = Arc::clone(client);
+    let semester_id = semester.id;
+    let gpa_handle = tokio::spawn(async move { get_gpa(&*shared_client, semester_id).await });
+    let subjects = fetch_subjects(client, semester.id, score_mapping_lists, rules).await?;
+    let gpa = gpa_handle.await.unwrap()?;
+    let report = GradeReport::new(semester, &subjects, gpa, &calculate_gpa(&subjects));
+    Ok((subjects, gpa, report))
+}
+
+/// Prints the grade report of each semester in the chosen format.
+async fn print_reports(
+    cli: &Cli,
+    config: &Config,
+    client: &Arc<dyn SchoolisApi>,
+    semesters: &[Semester],
+    score_mapping_lists: &Arc<HashMap<ScoreMappingId, Vec<ScoreMappingConfig>>>,
+) -> Result<(), Error> {
+    let mut reports = Vec::new();
+    for semester in semesters {
+        let (subjects, gpa, report) =
+            fetch_report(client, semester, score_mapping_lists, &config.rules).await?;
+        let diff = if config.history {
+            update_history(&report).unwrap_or_else(|err| {
+                warn!("Failed to update grade history: {err}");
+                None
+            })
+        } else {
+            None
+        };
+        if cli.format == OutputFormat::Table {
+            if cli.semesters.is_some() {
+                println!(
+                    "{}",
+                    format!(
+                        "{}, Semester {}",
+                        semester.academic_year(),
+                        semester.semester
+                    )
+                    .bold()
+                );
+            }
+            if let Some(diff) = &diff {
+                history::print_banner(diff, config);
+            }
+            if cli.explain {
+                for subject in &subjects {
+                    explain::print_subject(subject, &config.rules);
+                }
+                explain::print_gpa(&subjects, gpa);
+            } else {
+                for subject in &subjects {
+                    print_subject(subject, cli, config);
+                }
+                print_gpa(gpa, &calculate_gpa(&subjects));
+            }
+        }
+        reports.push(report);
+    }
+
+    match cli.format {
+        OutputFormat::Table => {}
+        // A list of reports if --semesters was given, even if it's only one
+        OutputFormat::Json if cli.semesters.is_some() => {
+            println!("{}", serde_json::to_string_pretty(&reports)?);
+        }
+        OutputFormat::Json => {
+            for report in &reports {
+                println!("{}", serde_json::to_string_pretty(report)?);
+            }
+        }
+        OutputFormat::Csv | OutputFormat::Tsv => {
+            let delimiter = if cli.format == OutputFormat::Csv {
+                b','
+            } else {
+                b'\t'
+            };
+            report::write_delimited(&reports, std::io::stdout().lock(), delimiter)?;
+        }
+    }
+    Ok(())
+}
+
+/// Snapshots `report` and returns what changed since the previous snapshot,
+/// if it is new.
+fn update_history(report: &GradeReport) -> Result<Option<Diff>, Error> {
+    let history = History::open()?;
+    let previous = history.previous(report, None)?;
+    if !history.save(report)? {
+        return Ok(None);
+    }
+    Ok(previous.map(|previous| Diff::new(&previous, report)))
+}
+
+/// Fetches every subject of a semester with its scores, weights and GPA.
+async fn fetch_subjects(
+    client: &Arc<dyn SchoolisApi>,
+    semester_id: u64,
+    score_mapping_lists: &Arc<HashMap<ScoreMappingId, Vec<ScoreMappingConfig>>>,
+    rules: &Rules,
+) -> Result<Vec<Subject>, Error> {
+    info!("Fetching subjects");
+    let shared_rules = Arc::new(rules.clone());
+    let shared_client = Arc::clone(client);
+    let subject_dynamic_scores_handle =
+        tokio::spawn(async move { get_subject_dynamic_scores(&*shared_client, semester_id).await });
+
+    let shared_client = Arc::clone(client);
+    let elective_pattern = rules.elective_pattern.clone();
+    let elective_class_ids_handle =
+        tokio::spawn(
+            async move { get_elective_class_ids(&*shared_client, &elective_pattern).await },
+        );
 
     info!("Fetching subject scores");
-    let subject_ids = get_subject_ids(&client, semester.id).await;
+    let subject_ids = get_subject_ids(&**client, semester_id).await?;
     let mut handles = Vec::new();
     for subject_id in subject_ids {
-        let client = Arc::clone(&client);
-        let score_mapping_lists = Arc::clone(&score_mapping_lists);
+        let client = Arc::clone(client);
+        let score_mapping_lists = Arc::clone(score_mapping_lists);
+        let rules = Arc::clone(&shared_rules);
         let handle = tokio::spawn(async move {
-            get_subject(&client, semester.id, subject_id, &score_mapping_lists).await
+            get_subject(
+                &*client,
+                semester_id,
+                subject_id,
+                &score_mapping_lists,
+                &rules,
+            )
+            .await
         });
         handles.push(handle);
     }
 
     let mut subjects = Vec::new();
-    let elective_class_ids = elective_class_ids_handle.await.unwrap();
-    let subject_dynamic_scores = subject_dynamic_scores_handle.await.unwrap();
+    let elective_class_ids = elective_class_ids_handle.await.unwrap()?;
+    let subject_dynamic_scores = subject_dynamic_scores_handle.await.unwrap()?;
     let results = join_all(handles).await;
     for result in results {
-        let mut subject = result.unwrap();
-        adjust_weights(&mut subject, &elective_class_ids);
-        overlay_subject(&mut subject, &subject_dynamic_scores, &score_mapping_lists);
+        let mut subject = result.unwrap()?;
+        adjust_weights(&mut subject, &elective_class_ids, rules);
+        overlay_subject(&mut subject, &subject_dynamic_scores, score_mapping_lists);
+        apply_gpa_override(&mut subject, rules);
         subjects.push(subject);
     }
+    resolve_score_mappings(
+        &mut subjects,
+        &rules.score_mapping_ids(),
+        score_mapping_lists,
+    );
+    Ok(subjects)
+}
 
-    for subject in &subjects {
-        print_subject(subject, &cli, &config);
-    }
-
-    let gpa = gpa_handle.await.unwrap();
-    let calculated_gpa = calculate_gpa(&subjects);
+fn print_gpa(gpa: f64, calculated_gpa: &CalculatedGPA) {
     if gpa.is_nan() {
         println!("GPA: Unreleased");
     } else {
@@ -176,14 +757,23 @@ async fn main() {
     );
 }
 
-fn select_semester(semesters: &[Semester]) -> Semester {
+fn select_semester(
+    semesters: &[Semester],
+    selector: Option<&SemesterSelector>,
+) -> Result<Semester, Error> {
+    if let Some(selector) = selector {
+        return selector.resolve(semesters).cloned();
+    }
+    if !std::io::stdin().is_terminal() {
+        // Nobody to ask, e.g. in a cron job or a pipe
+        return SemesterSelector::Current.resolve(semesters).cloned();
+    }
     let mut current_semester = 0;
     for (i, semester) in semesters.iter().enumerate().rev() {
         println!(
-            "{:2}: {}-{}, Semester {}",
+            "{:2}: {}, Semester {}",
             i,
-            semester.start_date.year(),
-            semester.end_date.year(),
+            semester.academic_year(),
             semester.semester,
         );
         if semester.is_now {
@@ -192,9 +782,14 @@ fn select_semester(semesters: &[Semester]) -> Semester {
     }
     let input = prompt_input!("Choose a semester [{current_semester}]: ");
     if !input.is_empty() {
-        current_semester = input.parse().expect("Input not an integer");
+        current_semester = input
+            .parse()
+            .map_err(|_| Error::input(format!("{input} is not an integer")))?;
     }
-    semesters[current_semester].clone()
+    semesters
+        .get(current_semester)
+        .cloned()
+        .ok_or_else(|| Error::input(format!("there is no semester {current_semester}")))
 }
 
 fn round_score(value: f64, decimal_places: u32) -> f64 {
@@ -219,7 +814,12 @@ fn print_subject(subject: &Subject, cli: &Cli, config: &Config) {
         ),
         subject.score_level.to_string(),
         subject.gpa.to_string(),
-        subject.score_mapping_list_id.to_string() + if subject.elective { " Elective" } else { "" },
+        subject.score_mapping_list_id.to_string()
+            + if subject.elective { " Elective" } else { "" }
+            + &subject
+                .exclusion_reason()
+                .map(|reason| format!(", not in GPA: {reason}"))
+                .unwrap_or_default(),
     )];
     for evaluation_project in &subject.evaluation_projects {
         if evaluation_project.score_is_null {
@@ -257,10 +857,18 @@ fn print_subject(subject: &Subject, cli: &Cli, config: &Config) {
             }
         }
     }
-    let table = Table::new(data)
+    // Subjects left out of the GPA are dimmed instead of colored by grade
+    if !subject.in_gpa {
+        for row in &mut data {
+            for cell in [&mut row.0, &mut row.1, &mut row.2, &mut row.3, &mut row.4] {
+                *cell = cell.ansi_strip().dimmed().to_string();
+            }
+        }
+    }
+    let mut table = Table::new(data);
+    table
         .with(Remove::row(Rows::first()))
-        .with(Style::rounded())
-        .to_string();
+        .with(Style::rounded());
     println!("{table}");
 }
 
@@ -291,13 +899,14 @@ fn get_evaluation_project_task_list_row(
     config: &Config,
 ) -> Vec<(String, String, String, String, String)> {
     let mut task_rows = Vec::new();
-    let learning_tasks: Vec<&LearningTask> = evaluation_project
+    let learning_tasks = evaluation_project
         .learning_task_and_exam_list
         .iter()
-        .filter(|task| task.score.is_some())
-        .collect();
-    for learning_task in &learning_tasks {
-        let weight = evaluation_project.adjusted_proportion / learning_tasks.len() as f64;
+        .zip(evaluation_project.task_proportions());
+    for (learning_task, weight) in learning_tasks {
+        let Some(weight) = weight else {
+            continue;
+        };
         let score = round_score(
             learning_task.score.unwrap_or(f64::NAN) / learning_task.total_score * 100.0,
             2,
@@ -342,21 +951,34 @@ fn colorize(string: &str, score_level: &str, color_scheme: &config::ColorScheme)
     string.color(color).to_string()
 }
 
-async fn login(config: &mut Login) -> reqwest::Client {
+/// Logs in again with the saved login info, or asks for it if there is none.
+async fn relogin(client: &ApiClient) -> Result<(), Error> {
+    let login_path = get_configuration_file_path("tls-xb", "login")?;
+    let mut login_info = if fs::metadata(&login_path).is_ok() {
+        config::get_login()?
+    } else {
+        // if the login file doesn't exist, do tls-xb login.
+        config::login()?
+    };
+    login(client, &mut login_info).await
+}
+
+async fn login(client: &ApiClient, config: &mut Login) -> Result<(), Error> {
     info!("Logging in");
-    let mut client;
+    // The cached responses may belong to another student
+    cache::clear()?;
     let login_limit = 3;
     for _ in 1..=login_limit {
-        client = client::login(config).await;
-        match client {
-            Ok(client) => {
-                config::save_login(config);
-                return client;
+        match client::login(client, config).await {
+            Ok(()) => {
+                config::save_login(config)?;
+                client.save()?;
+                return Ok(());
             }
             Err(LoginError::IncorrectLogin(msg)) => {
                 println!("{msg}");
                 println!("Sorry, try again.");
-                *config = config::login();
+                *config = config::login()?;
             }
             Err(LoginError::ErrorCode((msg, state))) => {
                 println!("{msg}");
@@ -366,11 +988,18 @@ async fn login(config: &mut Login) -> reqwest::Client {
                 println!("{msg}");
                 println!("Sorry, wrong captcha, try again.");
             }
+            Err(LoginError::Api(err)) => return Err(err.into()),
         }
     }
-    if let Ok(client) = client::login(config).await {
-        config::save_login(config);
-        return client;
+    match client::login(client, config).await {
+        Ok(()) => {
+            config::save_login(config)?;
+            client.save()?;
+            Ok(())
+        }
+        Err(LoginError::Api(err)) => Err(err.into()),
+        Err(_) => Err(Error::login(format!(
+            "{login_limit} incorrect login attempts."
+        ))),
     }
-    panic!("{login_limit} incorrect login attempts.");
 }
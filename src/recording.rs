@@ -0,0 +1,262 @@
+use crate::{
+    client::{ApiError, SchoolisApi},
+    error::Error,
+    private,
+};
+use async_trait::async_trait;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+/// A request and the response the server gave, stored as one JSON file per
+/// request in the recording directory.
+#[derive(Serialize, Deserialize)]
+pub struct Exchange {
+    pub method: String,
+    /// Relative to `/api/`, without the base URL so recordings work with any tenant
+    pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<serde_json::Value>,
+    pub response: serde_json::Value,
+}
+
+/// Query parameters and body fields that hold credentials.
+const SECRETS: [&str; 4] = ["captcha", "password", "name", "timestamp"];
+const REDACTED: &str = "REDACTED";
+
+/// Replaces credentials in the query of `path`.
+fn scrub_path(path: &str) -> String {
+    let Some((endpoint, query)) = path.split_once('?') else {
+        return path.to_string();
+    };
+    let query: Vec<String> = query
+        .split('&')
+        .map(|parameter| match parameter.split_once('=') {
+            Some((key, _)) if SECRETS.contains(&key) => format!("{key}={REDACTED}"),
+            _ => parameter.to_string(),
+        })
+        .collect();
+    format!("{endpoint}?{}", query.join("&"))
+}
+
+/// Replaces credentials in a request body, e.g. the login payload.
+fn scrub_body(body: &serde_json::Value) -> serde_json::Value {
+    let mut body = body.clone();
+    if let Some(fields) = body.as_object_mut() {
+        for (key, value) in fields.iter_mut() {
+            if SECRETS.contains(&key.as_str()) {
+                *value = REDACTED.into();
+            }
+        }
+    }
+    body
+}
+
+/// File name for the `index`th request, readable but safe on every platform.
+pub fn file_name(index: usize, method: &str, path: &str) -> String {
+    let endpoint: String = path
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .take(80)
+        .collect();
+    format!("{index:04}-{method}-{endpoint}.json")
+}
+
+pub fn json_files(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "json")
+        })
+        .collect();
+    paths.sort();
+    Ok(paths)
+}
+
+/// The index after the highest one in `dir`, so appending never overwrites
+/// an exchange even if earlier ones were deleted.
+fn next_index(dir: &Path) -> Result<usize, Error> {
+    let next = json_files(dir)?
+        .iter()
+        .filter_map(|path| {
+            let name = path.file_name()?.to_str()?;
+            name.split('-').next()?.parse::<usize>().ok()
+        })
+        .max()
+        .map_or(0, |index| index + 1);
+    Ok(next)
+}
+
+/// Writes every request `inner` makes and its response to a directory,
+/// with credentials scrubbed. Cookies never reach this layer, so they are
+/// never written.
+pub struct Recorder {
+    inner: Arc<dyn SchoolisApi>,
+    dir: PathBuf,
+    next: AtomicUsize,
+}
+
+impl Recorder {
+    pub fn new(inner: Arc<dyn SchoolisApi>, dir: &Path) -> Result<Recorder, Error> {
+        // Responses hold grades, keep them private like the cache
+        private::create_dir_all(dir)?;
+        // Append to an earlier recording in the same directory
+        let next = next_index(dir)?;
+        info!("Recording requests to {}", dir.display());
+        Ok(Recorder {
+            inner,
+            dir: dir.to_path_buf(),
+            next: AtomicUsize::new(next),
+        })
+    }
+
+    fn record(
+        &self,
+        method: &str,
+        path: &str,
+        body: Option<&serde_json::Value>,
+        response: &serde_json::Value,
+    ) -> Result<(), Error> {
+        let exchange = Exchange {
+            method: method.to_string(),
+            path: scrub_path(path),
+            body: body.map(scrub_body),
+            response: response.clone(),
+        };
+        let index = self.next.fetch_add(1, Ordering::SeqCst);
+        let file = self.dir.join(file_name(index, method, &exchange.path));
+        private::write(&file, serde_json::to_string_pretty(&exchange)?)?;
+        Ok(())
+    }
+
+    fn recorded(
+        &self,
+        method: &str,
+        path: &str,
+        body: Option<&serde_json::Value>,
+        response: Result<serde_json::Value, ApiError>,
+    ) -> Result<serde_json::Value, ApiError> {
+        // Failed requests have no response to record
+        if let Ok(response) = &response {
+            if let Err(err) = self.record(method, path, body, response) {
+                warn!("Failed to record {path}: {err}");
+            }
+        }
+        response
+    }
+}
+
+#[async_trait]
+impl SchoolisApi for Recorder {
+    async fn get(&self, path: &str) -> Result<serde_json::Value, ApiError> {
+        let response = self.inner.get(path).await;
+        self.recorded("GET", path, None, response)
+    }
+
+    async fn post(
+        &self,
+        path: &str,
+        body: &serde_json::Value,
+    ) -> Result<serde_json::Value, ApiError> {
+        let response = self.inner.post(path, body).await;
+        self.recorded("POST", path, Some(body), response)
+    }
+}
+
+/// Serves the responses of a recording instead of the server.
+pub struct Replay {
+    exchanges: Vec<Exchange>,
+}
+
+impl Replay {
+    pub fn open(dir: &Path) -> Result<Replay, Error> {
+        let mut exchanges = Vec::new();
+        for path in json_files(dir)? {
+            let exchange = fs::read_to_string(&path)?;
+            let exchange = serde_json::from_str(&exchange).map_err(|err| {
+                Error::input(format!("invalid recording {}: {err}", path.display()))
+            })?;
+            exchanges.push(exchange);
+        }
+        if exchanges.is_empty() {
+            return Err(Error::input(format!(
+                "{} has no recorded requests",
+                dir.display()
+            )));
+        }
+        info!(
+            "Replaying {} requests from {}",
+            exchanges.len(),
+            dir.display()
+        );
+        Ok(Replay { exchanges })
+    }
+
+    /// The latest response to the same request. Requests whose body differs,
+    /// such as schedules of a date range relative to today, fall back to the
+    /// latest response from the same endpoint.
+    fn replay(
+        &self,
+        method: &str,
+        path: &str,
+        body: Option<&serde_json::Value>,
+    ) -> Result<serde_json::Value, ApiError> {
+        let path = scrub_path(path);
+        let body = body.map(scrub_body);
+        let same_endpoint = || {
+            self.exchanges
+                .iter()
+                .rev()
+                .filter(|exchange| exchange.method == method && exchange.path == path)
+        };
+        let exchange = same_endpoint()
+            .find(|exchange| exchange.body == body)
+            .or_else(|| same_endpoint().next())
+            .ok_or_else(|| ApiError::NotRecorded(path.clone()))?;
+        debug!("{method} {path} from the recording");
+        Ok(exchange.response.clone())
+    }
+}
+
+#[async_trait]
+impl SchoolisApi for Replay {
+    async fn get(&self, path: &str) -> Result<serde_json::Value, ApiError> {
+        self.replay("GET", path, None)
+    }
+
+    async fn post(
+        &self,
+        path: &str,
+        body: &serde_json::Value,
+    ) -> Result<serde_json::Value, ApiError> {
+        self.replay("POST", path, Some(body))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn appends_after_the_highest_index() {
+        let dir = std::env::temp_dir().join(format!("tls-xb-recording-{}", std::process::id()));
+        private::create_dir_all(&dir).unwrap();
+        assert_eq!(next_index(&dir).unwrap(), 0);
+        // 0001 was deleted, counting files would overwrite 0002
+        for index in [0, 2] {
+            let file = dir.join(file_name(index, "GET", "Semester/GetSemester"));
+            fs::write(file, "{}").unwrap();
+        }
+        fs::write(dir.join("notes.txt"), "").unwrap();
+        assert_eq!(next_index(&dir).unwrap(), 3);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
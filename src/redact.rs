@@ -0,0 +1,477 @@
+use crate::{
+    error::Error,
+    private,
+    recording::{file_name, json_files, Exchange},
+};
+use directories::ProjectDirs;
+use log::info;
+use serde_json::{Map, Value};
+use std::{
+    fs,
+    io::{Read, Write},
+    path::Path,
+};
+
+/// Keys, lowercased without underscores, whose values identify the student.
+const ID_KEYS: [&str; 8] = [
+    "id",
+    "classid",
+    "subjectid",
+    "schoolsemesterid",
+    "semesterid",
+    "learningtaskid",
+    "taskid",
+    "studentid",
+];
+const STUDENT_NAME_KEYS: [&str; 6] = [
+    "studentname",
+    "studentename",
+    "studentcname",
+    "realname",
+    "username",
+    "nickname",
+];
+/// Class names are kept like subject names, which they usually equal, and
+/// electives are recognized by them
+const TEACHER_NAME_KEYS: [&str; 3] = ["teachername", "teacherename", "teachercname"];
+
+fn normalize(key: &str) -> String {
+    key.replace('_', "").to_lowercase()
+}
+
+/// HMAC-MD5 (RFC 2104) of `message`. Unlike `DefaultHasher` it is the same
+/// on every Rust release, so pseudonyms survive a toolchain upgrade.
+fn hmac_md5(key: &[u8], message: &[u8]) -> [u8; 16] {
+    let mut block = [0u8; 64];
+    if key.len() > block.len() {
+        block[..16].copy_from_slice(&md5::compute(key).0);
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let mut inner = md5::Context::new();
+    inner.consume(block.map(|byte| byte ^ 0x36));
+    inner.consume(message);
+    let mut outer = md5::Context::new();
+    outer.consume(block.map(|byte| byte ^ 0x5c));
+    outer.consume(inner.compute().0);
+    outer.compute().0
+}
+
+/// Replaces identifying names and ids in grade reports and recorded
+/// responses with pseudonyms, optionally adding noise to scores.
+///
+/// Pseudonyms are derived from a key kept in the data directory, so the same
+/// id or name gets the same pseudonym in every file redacted on this
+/// computer, but can't be traced back without the key.
+pub struct Redactor {
+    key: String,
+    /// Scores move by up to this many points
+    noise: Option<f64>,
+}
+
+impl Redactor {
+    pub fn new(noise: Option<f64>) -> Result<Redactor, Error> {
+        Ok(Redactor {
+            key: load_key()?,
+            noise,
+        })
+    }
+
+    fn hash(&self, kind: &str, value: &str) -> u64 {
+        let message = [kind.as_bytes(), &[0], value.as_bytes()].concat();
+        let digest = hmac_md5(self.key.as_bytes(), &message);
+        u64::from_be_bytes(digest[..8].try_into().unwrap())
+    }
+
+    fn numeric_id(&self, id: u64) -> u64 {
+        1_000_000 + self.hash("id", &id.to_string()) % 9_000_000
+    }
+
+    /// A pseudonym of the same kind, numeric ids stay numeric.
+    fn id(&self, value: &str) -> String {
+        match value.parse() {
+            Ok(id) => self.numeric_id(id).to_string(),
+            Err(_) => format!("{:016x}", self.hash("id", value)),
+        }
+    }
+
+    fn name(&self, prefix: &str, value: &str) -> String {
+        format!("{prefix} {:04x}", self.hash(prefix, value) % 0x10000)
+    }
+
+    /// A deterministic offset in `-noise..=noise`, so a score gets the same
+    /// noise in the report and in the responses it came from.
+    fn offset(&self, value: f64) -> f64 {
+        let Some(noise) = self.noise else {
+            return 0.0;
+        };
+        let unit = self.hash("noise", &value.to_string()) as f64 / u64::MAX as f64;
+        (unit * 2.0 - 1.0) * noise
+    }
+
+    pub fn redact(&self, value: &mut Value) {
+        match value {
+            Value::Object(object) => self.redact_object(object),
+            Value::Array(values) => {
+                for value in values {
+                    self.redact(value);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn redact_object(&self, object: &mut Map<String, Value>) {
+        for (key, value) in object.iter_mut() {
+            let key = normalize(key);
+            if ID_KEYS.contains(&key.as_str()) {
+                match value {
+                    Value::Number(number) => {
+                        if let Some(id) = number.as_u64() {
+                            *value = self.numeric_id(id).into();
+                        }
+                    }
+                    Value::String(id) => *id = self.id(id),
+                    _ => {}
+                }
+            } else if STUDENT_NAME_KEYS.contains(&key.as_str()) {
+                if let Value::String(name) = value {
+                    *name = self.name("Student", name);
+                }
+            } else if TEACHER_NAME_KEYS.contains(&key.as_str()) {
+                if let Value::String(name) = value {
+                    *name = self.name("Teacher", name);
+                }
+            } else {
+                self.redact(value);
+            }
+        }
+        if self.noise.is_some() {
+            self.add_noise(object);
+        }
+        // Recorded requests carry ids in their query too
+        if let Some(Value::String(path)) = object.get_mut("path") {
+            *path = self.redact_path(path);
+        }
+    }
+
+    /// Adds noise to the scores of a task, project or subject. Maximum
+    /// scores and proportions are left alone, but every score moves on its
+    /// own, so tasks no longer add up to their project and subject scores.
+    /// Scores without a maximum are percentages, and stay at most 100.
+    fn add_noise(&self, object: &mut Map<String, Value>) {
+        let has_score = object.contains_key("score");
+        let mut max_scores = std::collections::HashMap::new();
+        for (key, value) in object.iter() {
+            if let Some(max) = value.as_f64() {
+                max_scores.insert(normalize(key), max);
+            }
+        }
+        for (key, value) in object.iter_mut() {
+            let key = normalize(key);
+            // `total_score` is the score of a subject, but the maximum of a task
+            let max = match key.as_str() {
+                "score" => max_scores.get("totalscore").copied(),
+                "subjectscore" => max_scores.get("subjecttotalscore").copied(),
+                "totalscore" if !has_score => None,
+                _ => continue,
+            };
+            let Some(score) = value.as_f64() else {
+                continue;
+            };
+            // Extra credit may put a subject above 100 already
+            let max = max.unwrap_or(score.max(100.0));
+            let noisy = (score + self.offset(score)).clamp(0.0, max);
+            *value = ((noisy * 100.0).round() / 100.0).into();
+        }
+        let score = object.get("score").and_then(Value::as_f64);
+        let total_score = object.get("total_score").and_then(Value::as_f64);
+        if let (Some(score), Some(total_score), Some(percentage)) =
+            (score, total_score, object.get_mut("percentage"))
+        {
+            *percentage = (score / total_score * 100.0).into();
+        }
+    }
+
+    /// Replaces ids in the query of a request path.
+    pub fn redact_path(&self, path: &str) -> String {
+        let Some((endpoint, query)) = path.split_once('?') else {
+            return path.to_string();
+        };
+        let query: Vec<String> = query
+            .split('&')
+            .map(|parameter| match parameter.split_once('=') {
+                Some((key, value)) if ID_KEYS.contains(&normalize(key).as_str()) => {
+                    format!("{key}={}", self.id(value))
+                }
+                _ => parameter.to_string(),
+            })
+            .collect();
+        format!("{endpoint}?{}", query.join("&"))
+    }
+}
+
+/// The redaction key, created the first time it's needed.
+fn load_key() -> Result<String, Error> {
+    let project_dirs = ProjectDirs::from("rs", "", "tls-xb")
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "no home directory"))?;
+    let path = project_dirs.data_dir().join("redact-key");
+    if let Ok(key) = fs::read_to_string(&path) {
+        return Ok(key.trim().to_string());
+    }
+    let mut bytes = [0u8; 16];
+    getrandom::fill(&mut bytes).map_err(std::io::Error::from)?;
+    let key: String = bytes.iter().map(|byte| format!("{byte:02x}")).collect();
+    private::create_dir_all(project_dirs.data_dir())?;
+    private::write(&path, &key)?;
+    info!("Created redaction key {}", path.display());
+    Ok(key)
+}
+
+fn redact_json(redactor: &Redactor, json: &str) -> Result<Value, Error> {
+    let mut value: Value = serde_json::from_str(json)?;
+    redactor.redact(&mut value);
+    Ok(value)
+}
+
+/// Redacts every request of a recording into `output`. File names hold the
+/// request path, so they are derived from the redacted one.
+fn redact_recording(redactor: &Redactor, input: &Path, output: &Path) -> Result<(), Error> {
+    if output == input {
+        return Err(Error::input("refusing to redact a recording in place"));
+    }
+    fs::create_dir_all(output)?;
+    let mut count = 0;
+    for path in json_files(input)? {
+        let exchange: Exchange =
+            serde_json::from_value(redact_json(redactor, &fs::read_to_string(&path)?)?)?;
+        fs::write(
+            output.join(file_name(count, &exchange.method, &exchange.path)),
+            serde_json::to_string_pretty(&exchange)?,
+        )?;
+        count += 1;
+    }
+    println!("Redacted {count} requests to {}", output.display());
+    Ok(())
+}
+
+/// Redacts a JSON file, standard input if `input` is `None` or `-`, or
+/// every request of a recording directory. Files are written to standard
+/// output unless `output` is given, recordings to `<input>-redacted`.
+pub fn run(redactor: &Redactor, input: Option<&Path>, output: Option<&Path>) -> Result<(), Error> {
+    let input = input.filter(|input| *input != Path::new("-"));
+    if let Some(input) = input.filter(|input| input.is_dir()) {
+        let output = match output {
+            Some(output) => output.to_path_buf(),
+            None => {
+                let mut name = input.file_name().unwrap_or_default().to_os_string();
+                name.push("-redacted");
+                input.with_file_name(name)
+            }
+        };
+        return redact_recording(redactor, input, &output);
+    }
+    let json = match input {
+        Some(input) => fs::read_to_string(input)?,
+        None => {
+            let mut json = String::new();
+            std::io::stdin().read_to_string(&mut json)?;
+            json
+        }
+    };
+    let json = serde_json::to_string_pretty(&redact_json(redactor, &json)?)?;
+    match output {
+        Some(output) => fs::write(output, json)?,
+        None => writeln!(std::io::stdout(), "{json}")?,
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        check::check,
+        client::SchoolisApi,
+        config::Rules,
+        fetch_subjects,
+        gpa::{default_score_mapping_lists, get_gpa},
+        recording::Replay,
+        semester::get_semesters,
+    };
+    use serde_json::json;
+    use std::sync::Arc;
+
+    fn redactor(noise: Option<f64>) -> Redactor {
+        Redactor {
+            key: "0123456789abcdef0123456789abcdef".to_string(),
+            noise,
+        }
+    }
+
+    fn ok(data: Value) -> Value {
+        json!({ "state": 0, "msg": null, "data": data })
+    }
+
+    /// A recording of `tls-xb check` with one subject.
+    fn recording() -> Vec<Exchange> {
+        let task = |id: u64, name: &str, score: f64, total_score: f64| json!({ "id": id, "name": name, "score": score, "totalScore": total_score });
+        let project = |name: &str, proportion: f64, score: f64, gpa: f64, tasks: Value| {
+            json!({
+                "evaluationProjectEName": name,
+                "proportion": proportion,
+                "score": score,
+                "scoreLevel": "",
+                "gpa": gpa,
+                "scoreIsNull": false,
+                "learningTaskAndExamList": tasks,
+                "evaluationProjectList": [],
+            })
+        };
+        let exchange = |path: &str, response: Value| Exchange {
+            method: "GET".to_string(),
+            path: path.to_string(),
+            body: None,
+            response,
+        };
+        vec![
+            exchange(
+                "School/GetSchoolSemesters",
+                ok(json!([{
+                    "id": 20251,
+                    "year": 2025,
+                    "semester": 1,
+                    "isNow": true,
+                    "startDate": "2025-08-18T00:00:00",
+                    "endDate": "2026-01-16T00:00:00",
+                }])),
+            ),
+            exchange("DynamicScore/GetGpa?semesterId=20251", ok(json!(3.8))),
+            exchange(
+                "DynamicScore/GetStuSemesterDynamicScore?semesterId=20251",
+                ok(json!({ "studentSemesterDynamicScoreBasicDtos": [{
+                    "classId": 10000,
+                    "className": "AP Calculus BC",
+                    "subjectId": 100,
+                    "subjectName": "AP Calculus BC",
+                    "isInGrade": true,
+                    "subjectScore": 89.3,
+                    "scoreMappingId": 1,
+                    "subjectTotalScore": 100.0,
+                }]})),
+            ),
+            Exchange {
+                method: "POST".to_string(),
+                path: "Schedule/ListScheduleByParent".to_string(),
+                body: Some(json!({ "beginTime": "2026-10-10", "endTime": "2026-10-26" })),
+                response: ok(json!([])),
+            },
+            exchange(
+                "LearningTask/GetStuSubjectListForSelect?semesterId=20251",
+                ok(json!([{ "id": 100, "name": "AP Calculus BC" }])),
+            ),
+            exchange(
+                "LearningTask/GetList?semesterId=20251&subjectId=100&pageIndex=1&pageSize=1",
+                ok(json!({ "list": [{ "id": 500000, "name": "Unit 1 Test" }], "totalCount": 3 })),
+            ),
+            exchange(
+                "LearningTask/GetDetail?learningTaskId=500000",
+                ok(json!({
+                    "classId": 10000,
+                    "schoolSemesterId": 20251,
+                    "subjectId": 100,
+                    "subjectName": "AP Calculus BC",
+                })),
+            ),
+            exchange(
+                "DynamicScore/GetDynamicScoreDetail?classId=10000&subjectId=100&semesterId=20251",
+                ok(json!({ "evaluationProjectList": [
+                    project(
+                        "Formative",
+                        30.0,
+                        87.5,
+                        3.8,
+                        json!([
+                            task(500001, "Quiz 1", 9.0, 10.0),
+                            task(500002, "Quiz 2", 8.5, 10.0),
+                        ]),
+                    ),
+                    project(
+                        "Summative",
+                        70.0,
+                        90.0,
+                        4.2,
+                        json!([task(500000, "Unit 1 Test", 45.0, 50.0)]),
+                    ),
+                ]})),
+            ),
+        ]
+    }
+
+    #[test]
+    fn hmac_md5_matches_rfc_2104() {
+        let digest = hmac_md5(&[0x0b; 16], b"Hi There");
+        let hex: String = digest.iter().map(|byte| format!("{byte:02x}")).collect();
+        assert_eq!(hex, "9294727a3638bb1c13f48ef8158bfc9d");
+    }
+
+    #[test]
+    fn pseudonyms_are_stable() {
+        let redactor = redactor(None);
+        assert_eq!(redactor.id("20251"), redactor.id("20251"));
+        assert_ne!(redactor.id("20251"), "20251");
+        // Pinned, a different pseudonym would break earlier redactions
+        assert_eq!(redactor.name("Student", "Zhang San"), "Student 83f7");
+    }
+
+    #[tokio::test]
+    async fn redacted_recording_replays_and_checks() {
+        let root = std::env::temp_dir().join(format!("tls-xb-redact-{}", std::process::id()));
+        let input = root.join("recording");
+        let output = root.join("recording-redacted");
+        fs::create_dir_all(&input).unwrap();
+        for (index, exchange) in recording().iter().enumerate() {
+            let path = input.join(file_name(index, &exchange.method, &exchange.path));
+            fs::write(path, serde_json::to_string(exchange).unwrap()).unwrap();
+        }
+        redact_recording(&redactor(None), &input, &output).unwrap();
+
+        let replay: Arc<dyn SchoolisApi> = Arc::new(Replay::open(&output).unwrap());
+        let semesters = get_semesters(&*replay).await.unwrap();
+        assert_ne!(semesters[0].id, 20251);
+        let lists = Arc::new(default_score_mapping_lists());
+        let subjects = fetch_subjects(&replay, semesters[0].id, &lists, &Rules::default())
+            .await
+            .unwrap();
+        assert_eq!(subjects[0].subject_name, "AP Calculus BC");
+        let redacted = fs::read_to_string(json_files(&output).unwrap()[2].clone()).unwrap();
+        assert!(redacted.contains(r#""className": "AP Calculus BC""#));
+        assert_ne!(subjects[0].class_id, 10000);
+        let gpa = get_gpa(&*replay, semesters[0].id).await.unwrap();
+        let report = check(&subjects, gpa, &lists);
+        assert!(report.checks > 0);
+        assert!(report.discrepancies.is_empty());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn noise_keeps_scores_in_range() {
+        let redactor = redactor(Some(5.0));
+        for score in [0.0, 50.0, 97.0, 99.5, 100.0] {
+            let mut project = json!({ "score": score, "proportion": 30.0 });
+            redactor.redact(&mut project);
+            let noisy = project["score"].as_f64().unwrap();
+            assert!((0.0..=100.0).contains(&noisy), "{score} -> {noisy}");
+            assert!((noisy - score).abs() <= 5.0);
+            assert_eq!(project["proportion"], 30.0);
+        }
+        // Extra credit above 100 is not cut off
+        let mut report = json!({ "total_score": 102.0 });
+        redactor.redact(&mut report);
+        assert!(report["total_score"].as_f64().unwrap() <= 107.0);
+        let mut task = json!({ "score": 9.0, "totalScore": 10.0 });
+        redactor.redact(&mut task);
+        assert!(task["score"].as_f64().unwrap() <= 10.0);
+        assert_eq!(task["totalScore"], 10.0);
+    }
+}
@@ -0,0 +1,487 @@
+use crate::{
+    error::Error,
+    gpa::{score_level_from_score, CalculatedGPA, ScoreMappingConfig},
+    semester::Semester,
+    subject::{EvaluationProject, LearningTask, Subject, TaskWeighting},
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+
+/// Version of the [`GradeReport`] schema. Bump it whenever a field is
+/// renamed, removed or changes meaning; adding fields is backwards compatible.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// The full grade report of a semester, as emitted by `--format json`.
+///
+/// Scores and GPAs that are unreleased or can't be calculated are `null`.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct GradeReport {
+    pub schema_version: u32,
+    pub generated_at: DateTime<Utc>,
+    pub semester: SemesterReport,
+    pub subjects: Vec<SubjectReport>,
+    /// Official GPA from the server
+    pub gpa: Option<f64>,
+    pub calculated_gpa: CalculatedGpaReport,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SemesterReport {
+    pub id: u64,
+    pub year: u64,
+    pub semester: u64,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    pub is_now: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SubjectReport {
+    pub subject_name: String,
+    pub subject_id: u64,
+    pub class_id: u64,
+    pub total_score: Option<f64>,
+    pub extra_credit: Option<f64>,
+    pub score_level: String,
+    pub gpa: Option<f64>,
+    pub max_gpa: Option<f64>,
+    pub unweighted_gpa: Option<f64>,
+    pub unweighted_max_gpa: Option<f64>,
+    pub score_mapping: String,
+    /// Score mapping id from the server
+    pub score_mapping_id: Option<u64>,
+    pub elective: bool,
+    pub weight: f64,
+    pub in_gpa: bool,
+    /// Why the subject is left out of the GPA or forced into it
+    #[serde(default)]
+    pub in_gpa_reason: Option<String>,
+    pub evaluation_projects: Vec<EvaluationProjectReport>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct EvaluationProjectReport {
+    pub name: String,
+    /// Proportion of the subject as configured by the teacher
+    pub proportion: f64,
+    /// Proportion after excluding projects without a score
+    pub adjusted_proportion: Option<f64>,
+    pub score: Option<f64>,
+    pub score_level: String,
+    pub gpa: Option<f64>,
+    pub score_is_null: bool,
+    /// How the tasks add up to the score, `null` if no model reproduces it
+    #[serde(default)]
+    pub task_weighting: Option<TaskWeighting>,
+    pub learning_tasks: Vec<LearningTaskReport>,
+    pub evaluation_projects: Vec<EvaluationProjectReport>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct LearningTaskReport {
+    pub name: String,
+    pub score: Option<f64>,
+    pub total_score: f64,
+    pub percentage: Option<f64>,
+    pub score_level: String,
+    /// Proportion of the subject this task makes up
+    pub proportion: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CalculatedGpaReport {
+    pub weighted_gpa: Option<f64>,
+    pub max_gpa: Option<f64>,
+    pub unweighted_gpa: Option<f64>,
+    pub unweighted_max_gpa: Option<f64>,
+}
+
+/// Maps NaN, used internally for missing values, to `None`.
+fn finite(value: f64) -> Option<f64> {
+    value.is_finite().then_some(value)
+}
+
+impl GradeReport {
+    pub fn new(
+        semester: &Semester,
+        subjects: &[Subject],
+        gpa: f64,
+        calculated_gpa: &CalculatedGPA,
+    ) -> GradeReport {
+        GradeReport {
+            schema_version: SCHEMA_VERSION,
+            generated_at: Utc::now(),
+            semester: SemesterReport::from(semester),
+            subjects: subjects.iter().map(SubjectReport::from).collect(),
+            gpa: finite(gpa),
+            calculated_gpa: CalculatedGpaReport::from(calculated_gpa),
+        }
+    }
+}
+
+impl From<&Semester> for SemesterReport {
+    fn from(semester: &Semester) -> Self {
+        SemesterReport {
+            id: semester.id,
+            year: semester.year,
+            semester: semester.semester,
+            start_date: semester.start_date.date(),
+            end_date: semester.end_date.date(),
+            is_now: semester.is_now,
+        }
+    }
+}
+
+impl SemesterReport {
+    /// Academic year the semester belongs to, e.g. `2024-2025`
+    pub fn academic_year(&self) -> String {
+        format!("{}-{}", self.year, self.year + 1)
+    }
+}
+
+impl From<&Subject> for SubjectReport {
+    fn from(subject: &Subject) -> Self {
+        SubjectReport {
+            subject_name: subject.subject_name.clone(),
+            subject_id: subject.subject_id,
+            class_id: subject.class_id,
+            total_score: finite(subject.total_score),
+            extra_credit: finite(subject.extra_credit),
+            score_level: subject.score_level.clone(),
+            gpa: finite(subject.gpa),
+            max_gpa: finite(subject.max_gpa),
+            unweighted_gpa: finite(subject.unweighted_gpa),
+            unweighted_max_gpa: finite(subject.unweighted_max_gpa),
+            score_mapping: subject.score_mapping_list_id.to_string(),
+            score_mapping_id: subject.score_mapping_id,
+            elective: subject.elective,
+            weight: subject.weight,
+            in_gpa: subject.in_gpa,
+            in_gpa_reason: subject.in_gpa_reason.clone(),
+            evaluation_projects: subject
+                .evaluation_projects
+                .iter()
+                .map(|evaluation_project| {
+                    EvaluationProjectReport::new(evaluation_project, &subject.score_mapping_list)
+                })
+                .collect(),
+        }
+    }
+}
+
+impl EvaluationProjectReport {
+    fn new(
+        evaluation_project: &EvaluationProject,
+        score_mapping_list: &[ScoreMappingConfig],
+    ) -> EvaluationProjectReport {
+        let learning_tasks = evaluation_project
+            .learning_task_and_exam_list
+            .iter()
+            .zip(evaluation_project.task_proportions())
+            .map(|(learning_task, proportion)| {
+                LearningTaskReport::new(learning_task, proportion, score_mapping_list)
+            })
+            .collect();
+        EvaluationProjectReport {
+            name: evaluation_project.evaluation_project_e_name.clone(),
+            proportion: evaluation_project.proportion,
+            adjusted_proportion: finite(evaluation_project.adjusted_proportion)
+                .filter(|_| !evaluation_project.score_is_null),
+            score: (!evaluation_project.score_is_null).then_some(evaluation_project.score),
+            score_level: evaluation_project.score_level.clone(),
+            gpa: (!evaluation_project.score_is_null).then_some(evaluation_project.gpa),
+            score_is_null: evaluation_project.score_is_null,
+            task_weighting: evaluation_project.task_weighting,
+            learning_tasks,
+            evaluation_projects: evaluation_project
+                .evaluation_project_list
+                .iter()
+                .map(|evaluation_project| {
+                    EvaluationProjectReport::new(evaluation_project, score_mapping_list)
+                })
+                .collect(),
+        }
+    }
+}
+
+impl LearningTaskReport {
+    fn new(
+        learning_task: &LearningTask,
+        proportion: Option<f64>,
+        score_mapping_list: &[ScoreMappingConfig],
+    ) -> LearningTaskReport {
+        let percentage = learning_task
+            .score
+            .map(|score| score / learning_task.total_score * 100.0)
+            .and_then(finite);
+        LearningTaskReport {
+            name: learning_task.name.clone(),
+            score: learning_task.score,
+            total_score: learning_task.total_score,
+            percentage,
+            score_level: percentage
+                .map(|percentage| score_level_from_score(percentage, score_mapping_list))
+                .unwrap_or_default(),
+            proportion: proportion.and_then(finite),
+        }
+    }
+}
+
+impl From<&CalculatedGPA> for CalculatedGpaReport {
+    fn from(calculated_gpa: &CalculatedGPA) -> Self {
+        CalculatedGpaReport {
+            weighted_gpa: finite(calculated_gpa.weighted_gpa),
+            max_gpa: finite(calculated_gpa.max_gpa),
+            unweighted_gpa: finite(calculated_gpa.unweighted_gpa),
+            unweighted_max_gpa: finite(calculated_gpa.unweighted_max_gpa),
+        }
+    }
+}
+
+/// One row of the flattened, long-format report written by `--format csv`.
+#[derive(Serialize)]
+struct ReportRow<'a> {
+    semester_id: u64,
+    /// `subject`, `evaluation_project` or `learning_task`
+    level: &'static str,
+    /// Names of the parents, separated by ` / `
+    path: String,
+    name: &'a str,
+    score: Option<f64>,
+    total_score: Option<f64>,
+    percentage: Option<f64>,
+    score_level: &'a str,
+    gpa: Option<f64>,
+    proportion: Option<f64>,
+    adjusted_proportion: Option<f64>,
+}
+
+/// Writes one row per subject, evaluation project and learning task of
+/// every report, each following its parent.
+pub fn write_delimited(
+    reports: &[GradeReport],
+    writer: impl Write,
+    delimiter: u8,
+) -> Result<(), Error> {
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(delimiter)
+        .from_writer(writer);
+    for report in reports {
+        report.write_subjects(&mut writer)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Escapes a name for the `path` column, which joins names with ` / `, the
+/// same way what-if paths escape them: `/` as `\/` and `\` as `\\`.
+fn escape_name(name: &str) -> String {
+    name.replace('\\', "\\\\").replace('/', "\\/")
+}
+
+impl GradeReport {
+    fn write_subjects<W: Write>(&self, writer: &mut csv::Writer<W>) -> Result<(), Error> {
+        for subject in &self.subjects {
+            writer.serialize(ReportRow {
+                semester_id: self.semester.id,
+                level: "subject",
+                path: String::new(),
+                name: &subject.subject_name,
+                score: subject.total_score,
+                total_score: Some(100.0),
+                percentage: subject.total_score,
+                score_level: &subject.score_level,
+                gpa: subject.gpa,
+                proportion: None,
+                adjusted_proportion: None,
+            })?;
+            for evaluation_project in &subject.evaluation_projects {
+                self.write_evaluation_project(
+                    writer,
+                    &escape_name(&subject.subject_name),
+                    evaluation_project,
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_evaluation_project<W: Write>(
+        &self,
+        writer: &mut csv::Writer<W>,
+        path: &str,
+        evaluation_project: &EvaluationProjectReport,
+    ) -> Result<(), Error> {
+        writer.serialize(ReportRow {
+            semester_id: self.semester.id,
+            level: "evaluation_project",
+            path: path.to_string(),
+            name: &evaluation_project.name,
+            score: evaluation_project.score,
+            total_score: Some(100.0),
+            percentage: evaluation_project.score,
+            score_level: &evaluation_project.score_level,
+            gpa: evaluation_project.gpa,
+            proportion: Some(evaluation_project.proportion),
+            adjusted_proportion: evaluation_project.adjusted_proportion,
+        })?;
+        let path = format!("{path} / {}", escape_name(&evaluation_project.name));
+        for learning_task in &evaluation_project.learning_tasks {
+            writer.serialize(ReportRow {
+                semester_id: self.semester.id,
+                level: "learning_task",
+                path: path.clone(),
+                name: &learning_task.name,
+                score: learning_task.score,
+                total_score: Some(learning_task.total_score),
+                percentage: learning_task.percentage,
+                score_level: &learning_task.score_level,
+                gpa: None,
+                proportion: None,
+                adjusted_proportion: learning_task.proportion,
+            })?;
+        }
+        for sub_evaluation_project in &evaluation_project.evaluation_projects {
+            self.write_evaluation_project(writer, &path, sub_evaluation_project)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A schema version 1 report with a nested evaluation project and names
+    /// that need quoting in CSV.
+    fn report_json() -> serde_json::Value {
+        serde_json::from_str(
+            r#"{
+            "schema_version": 1,
+            "generated_at": "2025-03-01T08:00:00Z",
+            "semester": {
+                "id": 20242,
+                "year": 2024,
+                "semester": 2,
+                "start_date": "2025-02-17",
+                "end_date": "2025-06-27",
+                "is_now": true
+            },
+            "subjects": [{
+                "subject_name": "English, \"Advanced\"",
+                "subject_id": 101,
+                "class_id": 10001,
+                "total_score": 91.5,
+                "extra_credit": null,
+                "score_level": "A",
+                "gpa": 4.0,
+                "max_gpa": 4.0,
+                "unweighted_gpa": 4.0,
+                "unweighted_max_gpa": 4.0,
+                "score_mapping": "non-weighted",
+                "score_mapping_id": 2,
+                "elective": false,
+                "weight": 1.0,
+                "in_gpa": true,
+                "in_gpa_reason": null,
+                "evaluation_projects": [{
+                    "name": "Summative",
+                    "proportion": 60.0,
+                    "adjusted_proportion": 60.0,
+                    "score": 90.0,
+                    "score_level": "A",
+                    "gpa": 4.0,
+                    "score_is_null": false,
+                    "task_weighting": { "model": "points" },
+                    "learning_tasks": [],
+                    "evaluation_projects": [{
+                        "name": "Tests",
+                        "proportion": 100.0,
+                        "adjusted_proportion": 100.0,
+                        "score": 90.0,
+                        "score_level": "A",
+                        "gpa": 4.0,
+                        "score_is_null": false,
+                        "task_weighting": null,
+                        "learning_tasks": [{
+                            "name": "Unit 1\nTest",
+                            "score": 45.0,
+                            "total_score": 50.0,
+                            "percentage": 90.0,
+                            "score_level": "A",
+                            "proportion": 60.0
+                        }, {
+                            "name": "Unit 2 Test",
+                            "score": null,
+                            "total_score": 50.0,
+                            "percentage": null,
+                            "score_level": "",
+                            "proportion": null
+                        }],
+                        "evaluation_projects": []
+                    }]
+                }]
+            }],
+            "gpa": 4.0,
+            "calculated_gpa": {
+                "weighted_gpa": 4.0,
+                "max_gpa": 4.0,
+                "unweighted_gpa": 4.0,
+                "unweighted_max_gpa": 4.0
+            }
+        }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn json_round_trip_is_pinned_to_schema_version() {
+        // Changing the schema must bump SCHEMA_VERSION and update this test
+        assert_eq!(SCHEMA_VERSION, 1);
+        let report: GradeReport = serde_json::from_value(report_json()).unwrap();
+        assert_eq!(report.schema_version, SCHEMA_VERSION);
+        assert_eq!(serde_json::to_value(&report).unwrap(), report_json());
+    }
+
+    #[test]
+    fn csv_has_nested_paths_and_quoting() {
+        let report: GradeReport = serde_json::from_value(report_json()).unwrap();
+        let mut csv = Vec::new();
+        write_delimited(&[report], &mut csv, b',').unwrap();
+        let expected = "\
+semester_id,level,path,name,score,total_score,percentage,score_level,gpa,proportion,adjusted_proportion
+20242,subject,,\"English, \"\"Advanced\"\"\",91.5,100.0,91.5,A,4.0,,
+20242,evaluation_project,\"English, \"\"Advanced\"\"\",Summative,90.0,100.0,90.0,A,4.0,60.0,60.0
+20242,evaluation_project,\"English, \"\"Advanced\"\" / Summative\",Tests,90.0,100.0,90.0,A,4.0,100.0,100.0
+20242,learning_task,\"English, \"\"Advanced\"\" / Summative / Tests\",\"Unit 1
+Test\",45.0,50.0,90.0,A,,,60.0
+20242,learning_task,\"English, \"\"Advanced\"\" / Summative / Tests\",Unit 2 Test,,50.0,,,,,
+";
+        assert_eq!(String::from_utf8(csv).unwrap(), expected);
+    }
+
+    #[test]
+    fn tsv_uses_tabs() {
+        let report: GradeReport = serde_json::from_value(report_json()).unwrap();
+        let mut tsv = Vec::new();
+        write_delimited(&[report], &mut tsv, b'\t').unwrap();
+        let tsv = String::from_utf8(tsv).unwrap();
+        assert!(tsv.starts_with("semester_id\tlevel\tpath\tname\t"));
+        assert!(tsv.contains("20242\tsubject\t\t\"English, \"\"Advanced\"\"\"\t91.5\t"));
+    }
+
+    #[test]
+    fn csv_escapes_slashes_in_path_names() {
+        let mut report: GradeReport = serde_json::from_value(report_json()).unwrap();
+        let summative = &mut report.subjects[0].evaluation_projects[0];
+        summative.name = "Quizzes / Tests".to_string();
+        summative.evaluation_projects[0].name = r"Unit\Final".to_string();
+        let mut csv = Vec::new();
+        write_delimited(&[report], &mut csv, b',').unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let path = r#""English, ""Advanced"" / Quizzes \/ Tests / Unit\\Final""#;
+        assert!(csv.contains(&format!("20242,learning_task,{path},Unit 2 Test,")));
+        assert!(csv.contains(",evaluation_project,\"English, \"\"Advanced\"\"\",Quizzes / Tests,"));
+    }
+}
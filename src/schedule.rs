@@ -0,0 +1,308 @@
+use crate::{
+    calendar::{school_now, school_time, Block, Calendar},
+    client::SchoolisApi,
+    error::Error,
+};
+use chrono::{DateTime, Datelike, Days, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+use chrono_tz::Tz;
+use colored::Colorize;
+use itertools::Itertools;
+use serde::Serialize;
+use std::{fmt, str::FromStr};
+use tabled::{builder::Builder, settings::Style};
+
+/// How far ahead `tls-xb next` looks, long enough to cover a holiday week.
+const NEXT_CLASS_DAYS: u64 = 14;
+
+/// The days `tls-xb schedule` shows.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ScheduleRange {
+    Today,
+    Tomorrow,
+    /// Monday to Sunday of the current week
+    Week,
+    Date(NaiveDate),
+}
+
+/// Parses `today`, `tomorrow`, `week` or a date like `2025-03-03`.
+impl FromStr for ScheduleRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "today" => Ok(ScheduleRange::Today),
+            "tomorrow" => Ok(ScheduleRange::Tomorrow),
+            "week" => Ok(ScheduleRange::Week),
+            _ => s
+                .parse()
+                .map(ScheduleRange::Date)
+                .map_err(|_| format!("{s} is not today, tomorrow, week or YYYY-MM-DD")),
+        }
+    }
+}
+
+impl ScheduleRange {
+    fn days(&self, today: NaiveDate) -> Vec<NaiveDate> {
+        match self {
+            ScheduleRange::Today => vec![today],
+            ScheduleRange::Tomorrow => vec![today + Days::new(1)],
+            ScheduleRange::Week => {
+                let monday = today - Days::new(today.weekday().num_days_from_monday().into());
+                monday.iter_days().take(7).collect()
+            }
+            ScheduleRange::Date(date) => vec![*date],
+        }
+    }
+}
+
+impl fmt::Display for ScheduleRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScheduleRange::Today => write!(f, "today"),
+            ScheduleRange::Tomorrow => write!(f, "tomorrow"),
+            ScheduleRange::Week => write!(f, "this week"),
+            ScheduleRange::Date(date) => write!(f, "on {}", date.format("%a %Y-%m-%d")),
+        }
+    }
+}
+
+/// A class in the schedule, as emitted by `--format json`.
+#[derive(Serialize)]
+pub struct ClassReport {
+    pub class_id: u64,
+    pub class_name: String,
+    pub begin_time: DateTime<FixedOffset>,
+    pub end_time: DateTime<FixedOffset>,
+}
+
+impl ClassReport {
+    fn new(block: &Block, tz: Tz) -> ClassReport {
+        ClassReport {
+            class_id: block.id,
+            class_name: block.class_name.clone(),
+            begin_time: school_time(tz, block.begin_time).fixed_offset(),
+            end_time: school_time(tz, block.end_time).fixed_offset(),
+        }
+    }
+}
+
+/// `tls-xb next --format json`, `class` is `null` if there is none.
+#[derive(Serialize)]
+struct NextClassReport {
+    class: Option<ClassReport>,
+    starts_in_minutes: Option<i64>,
+}
+
+/// Blocks from `first` to `last`, school dates inclusive, in order.
+async fn fetch_blocks(
+    api: &dyn SchoolisApi,
+    first: NaiveDate,
+    last: NaiveDate,
+    high_school: bool,
+) -> Result<Vec<Block>, Error> {
+    let mut blocks = Calendar::new(api, first, last, high_school).await?.blocks;
+    blocks.retain(|block| (first..=last).contains(&block.begin_time.date()));
+    blocks.sort_by_key(|block| block.begin_time);
+    Ok(blocks)
+}
+
+pub async fn schedule(
+    api: &dyn SchoolisApi,
+    tz: Tz,
+    range: &ScheduleRange,
+    high_school: bool,
+    json: bool,
+) -> Result<(), Error> {
+    let now = school_now(tz);
+    let days = range.days(now.date());
+    let blocks = fetch_blocks(api, days[0], days[days.len() - 1], high_school).await?;
+    if json {
+        let classes: Vec<ClassReport> = blocks
+            .iter()
+            .map(|block| ClassReport::new(block, tz))
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&classes)?);
+    } else if blocks.is_empty() {
+        println!("No classes {range}");
+    } else {
+        print_timetable(&blocks, &days, now);
+    }
+    Ok(())
+}
+
+/// Prints a grid with a row per block time and a column per day, leaving
+/// out weekend days without classes. The class in progress is highlighted.
+fn print_timetable(blocks: &[Block], days: &[NaiveDate], now: NaiveDateTime) {
+    let days: Vec<NaiveDate> = days
+        .iter()
+        .copied()
+        .filter(|day| {
+            !matches!(day.weekday(), Weekday::Sat | Weekday::Sun)
+                || blocks.iter().any(|block| block.begin_time.date() == *day)
+        })
+        .collect();
+    let times: Vec<(NaiveTime, NaiveTime)> = blocks
+        .iter()
+        .map(|block| (block.begin_time.time(), block.end_time.time()))
+        .sorted()
+        .dedup()
+        .collect();
+
+    let mut builder = Builder::default();
+    let mut header = vec!["Time".to_string()];
+    header.extend(days.iter().map(|day| {
+        let label = day.format("%a %m-%d").to_string();
+        if *day == now.date() {
+            label.bold().to_string()
+        } else {
+            label
+        }
+    }));
+    builder.push_record(header);
+    for (begin_time, end_time) in times {
+        let mut record = vec![format!(
+            "{}-{}",
+            begin_time.format("%H:%M"),
+            end_time.format("%H:%M")
+        )];
+        for day in &days {
+            let cell = blocks
+                .iter()
+                .filter(|block| {
+                    block.begin_time.date() == *day
+                        && block.begin_time.time() == begin_time
+                        && block.end_time.time() == end_time
+                })
+                .map(|block| {
+                    if block.begin_time <= now && now < block.end_time {
+                        format!("{} (now)", block.class_name)
+                            .green()
+                            .bold()
+                            .to_string()
+                    } else {
+                        block.class_name.clone()
+                    }
+                })
+                .join("\n");
+            record.push(cell);
+        }
+        builder.push_record(record);
+    }
+    let table = builder.build().with(Style::rounded()).to_string();
+    println!("{table}");
+}
+
+/// Whole minutes until a class starts, rounded up.
+fn minutes_until(until: chrono::Duration) -> i64 {
+    (until.num_seconds() + 59) / 60
+}
+
+/// Minutes as `2d 3h`, `1h 5m` or `25m`.
+fn format_minutes(minutes: i64) -> String {
+    let (days, hours, minutes) = (minutes / 1440, minutes / 60 % 24, minutes % 60);
+    match (days, hours) {
+        (0, 0) => format!("{minutes}m"),
+        (0, _) => format!("{hours}h {minutes}m"),
+        _ => format!("{days}d {hours}h"),
+    }
+}
+
+/// Prints the next class to start and how long until it does, on one line
+/// so it fits a shell prompt or status bar.
+pub async fn next(
+    api: &dyn SchoolisApi,
+    tz: Tz,
+    high_school: bool,
+    json: bool,
+) -> Result<(), Error> {
+    let now = school_now(tz);
+    // Across a daylight saving change the school times are an hour off
+    let starts_in = |block: &Block| school_time(tz, block.begin_time) - school_time(tz, now);
+    let today = now.date();
+    let blocks = fetch_blocks(api, today, today + Days::new(NEXT_CLASS_DAYS), high_school).await?;
+    let next = blocks.iter().find(|block| block.begin_time > now);
+    if json {
+        let report = NextClassReport {
+            class: next.map(|block| ClassReport::new(block, tz)),
+            starts_in_minutes: next.map(|block| minutes_until(starts_in(block))),
+        };
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+    let Some(next) = next else {
+        println!("No classes in the next {NEXT_CLASS_DAYS} days");
+        return Ok(());
+    };
+    let start = if next.begin_time.date() == today {
+        next.begin_time.format("%H:%M")
+    } else {
+        next.begin_time.format("%a %H:%M")
+    };
+    println!(
+        "{} in {} at {start}",
+        next.class_name,
+        format_minutes(minutes_until(starts_in(next)))
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(date: &str) -> NaiveDate {
+        date.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_ranges() {
+        assert_eq!("today".parse(), Ok(ScheduleRange::Today));
+        assert_eq!("tomorrow".parse(), Ok(ScheduleRange::Tomorrow));
+        assert_eq!("week".parse(), Ok(ScheduleRange::Week));
+        assert_eq!(
+            "2025-03-03".parse(),
+            Ok(ScheduleRange::Date(date("2025-03-03")))
+        );
+        assert!("next week".parse::<ScheduleRange>().is_err());
+        assert!("2025-02-30".parse::<ScheduleRange>().is_err());
+    }
+
+    #[test]
+    fn tomorrow_crosses_months() {
+        assert_eq!(
+            ScheduleRange::Tomorrow.days(date("2025-02-28")),
+            [date("2025-03-01")]
+        );
+    }
+
+    #[test]
+    fn week_runs_monday_to_sunday() {
+        let week: Vec<NaiveDate> = date("2025-03-03").iter_days().take(7).collect();
+        // Monday, a weekday and Sunday all belong to the same week
+        for today in ["2025-03-03", "2025-03-05", "2025-03-09"] {
+            assert_eq!(ScheduleRange::Week.days(date(today)), week, "{today}");
+        }
+        assert_eq!(
+            ScheduleRange::Week.days(date("2025-03-10"))[0],
+            date("2025-03-10")
+        );
+    }
+
+    #[test]
+    fn minutes_round_up() {
+        assert_eq!(minutes_until(chrono::Duration::seconds(0)), 0);
+        assert_eq!(minutes_until(chrono::Duration::seconds(1)), 1);
+        assert_eq!(minutes_until(chrono::Duration::seconds(60)), 1);
+        assert_eq!(minutes_until(chrono::Duration::seconds(61)), 2);
+    }
+
+    #[test]
+    fn formats_minutes() {
+        assert_eq!(format_minutes(0), "0m");
+        assert_eq!(format_minutes(59), "59m");
+        assert_eq!(format_minutes(60), "1h 0m");
+        assert_eq!(format_minutes(65), "1h 5m");
+        assert_eq!(format_minutes(1440), "1d 0h");
+        assert_eq!(format_minutes(3060), "2d 3h");
+    }
+}
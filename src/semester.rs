@@ -1,6 +1,11 @@
-use crate::calendar::date_parser;
-use chrono::{DateTime, FixedOffset};
+use crate::{
+    calendar::date_parser,
+    client::{self, ApiError, SchoolisApi},
+    error::Error,
+};
+use chrono::NaiveDateTime;
 use serde::Deserialize;
+use std::str::FromStr;
 
 #[derive(Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
@@ -9,24 +14,293 @@ pub struct Semester {
     pub year: u64,
     pub semester: u64,
     pub is_now: bool,
+    /// In the school's time zone
     #[serde(deserialize_with = "date_parser")]
-    pub start_date: DateTime<FixedOffset>,
+    pub start_date: NaiveDateTime,
     #[serde(deserialize_with = "date_parser")]
-    pub end_date: DateTime<FixedOffset>,
+    pub end_date: NaiveDateTime,
 }
 
-pub async fn get_semesters(client: &reqwest::Client) -> Vec<Semester> {
-    let response: serde_json::Value = client
-        .get("https://tsinglanstudent.schoolis.cn/api/School/GetSchoolSemesters")
-        .send()
-        .await
-        .unwrap()
-        .json()
-        .await
-        .unwrap();
-    serde_json::from_value(response["data"].clone()).expect("Failed to get semesters")
+impl Semester {
+    /// Academic year the semester belongs to, e.g. `2024-2025`
+    pub fn academic_year(&self) -> String {
+        format!("{}-{}", self.year, self.year + 1)
+    }
+}
+
+pub async fn get_semesters(api: &dyn SchoolisApi) -> Result<Vec<Semester>, ApiError> {
+    client::get(api, "School/GetSchoolSemesters").await
 }
 
 pub fn get_current_semester(semesters: &[Semester]) -> Option<&Semester> {
     semesters.iter().find(|s| s.is_now)
 }
+
+/// A semester given on the command line.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SemesterSelector {
+    /// The semester that is in progress
+    Current,
+    /// An index from the semester list, written `#3`
+    Index(usize),
+    /// A semester id
+    Id(u64),
+    /// An academic year by its first year, and optionally a semester of it
+    Year { year: u64, semester: Option<u64> },
+}
+
+/// Parses `current`, an index like `#3`, an id, `2024-2025` or `2024-2025:1`.
+impl FromStr for SemesterSelector {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("current") {
+            return Ok(SemesterSelector::Current);
+        }
+        if let Some(index) = s.strip_prefix('#') {
+            return index
+                .parse()
+                .map(SemesterSelector::Index)
+                .map_err(|_| format!("{s} is not an index"));
+        }
+        if let Ok(id) = s.parse() {
+            return Ok(SemesterSelector::Id(id));
+        }
+        let invalid = || format!("{s} is not #INDEX, an id, `current` or YYYY-YYYY[:SEMESTER]");
+        let (years, semester) = match s.split_once(':') {
+            Some((years, semester)) => (years, Some(semester.parse().map_err(|_| invalid())?)),
+            None => (s, None),
+        };
+        let (first_year, second_year) = years.split_once('-').ok_or_else(invalid)?;
+        let year: u64 = first_year.parse().map_err(|_| invalid())?;
+        if second_year.parse::<u64>().map_err(|_| invalid())? != year + 1 {
+            return Err(format!("{years} is not an academic year"));
+        }
+        Ok(SemesterSelector::Year { year, semester })
+    }
+}
+
+impl SemesterSelector {
+    /// Every semester the selector matches, in the order of `semesters`.
+    fn matches<'a>(&self, semesters: &'a [Semester]) -> Vec<&'a Semester> {
+        match *self {
+            SemesterSelector::Current => get_current_semester(semesters).into_iter().collect(),
+            SemesterSelector::Index(index) => semesters.get(index).into_iter().collect(),
+            SemesterSelector::Id(id) => semesters.iter().filter(|s| s.id == id).collect(),
+            SemesterSelector::Year { year, semester } => semesters
+                .iter()
+                .filter(|s| {
+                    s.year == year && semester.is_none_or(|semester| s.semester == semester)
+                })
+                .collect(),
+        }
+    }
+
+    /// The one semester the selector matches.
+    pub fn resolve<'a>(&self, semesters: &'a [Semester]) -> Result<&'a Semester, Error> {
+        match self.matches(semesters)[..] {
+            [semester] => Ok(semester),
+            [] => Err(Error::input(format!("there is no semester {self}"))),
+            _ => Err(Error::input(format!("{self} is more than one semester"))),
+        }
+    }
+}
+
+impl std::fmt::Display for SemesterSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SemesterSelector::Current => write!(f, "current"),
+            SemesterSelector::Index(index) => write!(f, "#{index}"),
+            SemesterSelector::Id(id) => write!(f, "{id}"),
+            SemesterSelector::Year {
+                year,
+                semester: None,
+            } => write!(f, "{year}-{}", year + 1),
+            SemesterSelector::Year {
+                year,
+                semester: Some(semester),
+            } => write!(f, "{year}-{}:{semester}", year + 1),
+        }
+    }
+}
+
+/// An inclusive range of semesters given on the command line,
+/// either end may be left open.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SemesterRange {
+    from: Option<SemesterSelector>,
+    to: Option<SemesterSelector>,
+}
+
+/// Parses `all`, a single selector, or `FROM..TO` where either end may be
+/// left out, e.g. `2023-2024..current`.
+impl FromStr for SemesterRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("all") {
+            return Ok(SemesterRange {
+                from: None,
+                to: None,
+            });
+        }
+        let parse_end = |end: &str| {
+            if end.trim().is_empty() {
+                Ok(None)
+            } else {
+                end.parse().map(Some)
+            }
+        };
+        match s.split_once("..") {
+            Some((from, to)) => Ok(SemesterRange {
+                from: parse_end(from)?,
+                to: parse_end(to)?,
+            }),
+            None => {
+                let selector: SemesterSelector = s.parse()?;
+                Ok(SemesterRange {
+                    from: Some(selector.clone()),
+                    to: Some(selector),
+                })
+            }
+        }
+    }
+}
+
+/// Every semester in any of the ranges, from oldest to newest.
+pub fn select_semesters(
+    semesters: &[Semester],
+    ranges: &[SemesterRange],
+) -> Result<Vec<Semester>, Error> {
+    let start_dates = |selector: &SemesterSelector| {
+        let matches = selector.matches(semesters);
+        if matches.is_empty() {
+            return Err(Error::input(format!("there is no semester {selector}")));
+        }
+        Ok(matches.into_iter().map(|semester| semester.start_date))
+    };
+    let mut selected: Vec<Semester> = Vec::new();
+    for range in ranges {
+        let from = match &range.from {
+            Some(from) => start_dates(from)?.min(),
+            None => None,
+        };
+        let to = match &range.to {
+            Some(to) => start_dates(to)?.max(),
+            None => None,
+        };
+        for semester in semesters {
+            let in_range = from.is_none_or(|from| semester.start_date >= from)
+                && to.is_none_or(|to| semester.start_date <= to);
+            if in_range && !selected.iter().any(|s| s.id == semester.id) {
+                selected.push(semester.clone());
+            }
+        }
+    }
+    selected.sort_by_key(|semester| semester.start_date);
+    Ok(selected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn semester(id: u64, year: u64, semester: u64, is_now: bool) -> Semester {
+        let (start, end) = if semester == 1 {
+            (format!("{year}-09-01"), format!("{}-01-20", year + 1))
+        } else {
+            (format!("{}-02-20", year + 1), format!("{}-07-01", year + 1))
+        };
+        serde_json::from_value(json!({
+            "id": id,
+            "year": year,
+            "semester": semester,
+            "isNow": is_now,
+            "startDate": format!("{start}T00:00:00"),
+            "endDate": format!("{end}T00:00:00"),
+        }))
+        .unwrap()
+    }
+
+    fn semesters() -> Vec<Semester> {
+        vec![
+            semester(101, 2023, 1, false),
+            semester(102, 2023, 2, false),
+            semester(103, 2024, 1, true),
+            semester(104, 2024, 2, false),
+        ]
+    }
+
+    fn parse(s: &str) -> SemesterSelector {
+        s.parse().unwrap()
+    }
+
+    fn ids(semesters: &[Semester]) -> Vec<u64> {
+        semesters.iter().map(|semester| semester.id).collect()
+    }
+
+    #[test]
+    fn parses_each_selector_form() {
+        assert_eq!(parse("current"), SemesterSelector::Current);
+        assert_eq!(parse(" Current "), SemesterSelector::Current);
+        assert_eq!(parse("#2"), SemesterSelector::Index(2));
+        assert_eq!(parse("2"), SemesterSelector::Id(2));
+        assert_eq!(parse("103"), SemesterSelector::Id(103));
+        assert_eq!(
+            parse("2024-2025"),
+            SemesterSelector::Year {
+                year: 2024,
+                semester: None
+            }
+        );
+        assert_eq!(
+            parse("2024-2025:2"),
+            SemesterSelector::Year {
+                year: 2024,
+                semester: Some(2)
+            }
+        );
+        for invalid in ["#", "#-1", "2024-2026", "2024-2025:", "spring"] {
+            assert!(invalid.parse::<SemesterSelector>().is_err(), "{invalid}");
+        }
+        for selector in ["current", "#2", "103", "2024-2025", "2024-2025:2"] {
+            assert_eq!(parse(selector).to_string(), selector);
+        }
+    }
+
+    #[test]
+    fn indices_and_ids_resolve_separately() {
+        let semesters = semesters();
+        assert_eq!(parse("#0").resolve(&semesters).unwrap().id, 101);
+        assert_eq!(parse("#3").resolve(&semesters).unwrap().id, 104);
+        assert!(parse("#4").resolve(&semesters).is_err());
+        assert_eq!(parse("102").resolve(&semesters).unwrap().id, 102);
+        // A small id is not taken as an index
+        assert!(parse("1").resolve(&semesters).is_err());
+        assert_eq!(parse("current").resolve(&semesters).unwrap().id, 103);
+        assert_eq!(parse("2023-2024:2").resolve(&semesters).unwrap().id, 102);
+        assert!(parse("2023-2024").resolve(&semesters).is_err());
+    }
+
+    #[test]
+    fn selects_ranges() {
+        let semesters = semesters();
+        let select = |ranges: &[&str]| {
+            let ranges: Vec<SemesterRange> = ranges.iter().map(|r| r.parse().unwrap()).collect();
+            ids(&select_semesters(&semesters, &ranges).unwrap())
+        };
+        assert_eq!(select(&["all"]), [101, 102, 103, 104]);
+        assert_eq!(select(&["2023-2024"]), [101, 102]);
+        assert_eq!(select(&["2023-2024:2..current"]), [102, 103]);
+        assert_eq!(select(&["..#1"]), [101, 102]);
+        assert_eq!(select(&["103.."]), [103, 104]);
+        assert_eq!(select(&["#3", "2023-2024:1"]), [101, 104]);
+        assert_eq!(select(&["current", "2024-2025"]), [103, 104]);
+        assert!("2023-2024:x..current".parse::<SemesterRange>().is_err());
+        let missing: Vec<SemesterRange> = vec!["#9..".parse().unwrap()];
+        assert!(select_semesters(&semesters, &missing).is_err());
+    }
+}
@@ -0,0 +1,104 @@
+use crate::{
+    client::{ApiClient, ApiError, SchoolisApi},
+    relogin,
+};
+use async_trait::async_trait;
+use log::info;
+use std::{future::Future, sync::Arc};
+use tokio::sync::Mutex;
+
+/// Logs in again when the server rejects the session, then retries the
+/// request once. The saved session is only checked by requests that reach
+/// the server, so cached responses don't need a login.
+pub struct Session {
+    client: Arc<ApiClient>,
+    inner: Arc<dyn SchoolisApi>,
+    /// How often the session was renewed, so requests rejected at the same
+    /// time log in once
+    logins: Mutex<u64>,
+}
+
+impl Session {
+    /// Sends requests through `inner`, and logs in again with `client`.
+    pub fn new(client: Arc<ApiClient>, inner: Arc<dyn SchoolisApi>) -> Session {
+        Session {
+            client,
+            inner,
+            logins: Mutex::new(0),
+        }
+    }
+
+    async fn send<F, R>(&self, request: F) -> Result<serde_json::Value, ApiError>
+    where
+        F: Fn() -> R,
+        R: Future<Output = Result<serde_json::Value, ApiError>>,
+    {
+        let logins = *self.logins.lock().await;
+        let response = request().await;
+        let Some(rejection) = rejection(&response) else {
+            return response;
+        };
+        let mut current = self.logins.lock().await;
+        // Someone else logged in while this request was on its way
+        if *current == logins {
+            if !self.client.is_empty() {
+                info!("Saved session rejected: {rejection}");
+            }
+            relogin(&self.client)
+                .await
+                .map_err(|err| ApiError::Relogin(Box::new(err)))?;
+            *current += 1;
+        }
+        drop(current);
+        request().await
+    }
+}
+
+/// Why the server rejected the session, if it did, either by the HTTP
+/// status or by the state of the envelope.
+fn rejection(response: &Result<serde_json::Value, ApiError>) -> Option<String> {
+    match response {
+        Ok(body) => ApiError::from_body(body)
+            .filter(ApiError::needs_login)
+            .map(|err| err.to_string()),
+        Err(err) if err.needs_login() => Some(err.to_string()),
+        Err(_) => None,
+    }
+}
+
+#[async_trait]
+impl SchoolisApi for Session {
+    async fn get(&self, path: &str) -> Result<serde_json::Value, ApiError> {
+        self.send(|| self.inner.get(path)).await
+    }
+
+    async fn post(
+        &self,
+        path: &str,
+        body: &serde_json::Value,
+    ) -> Result<serde_json::Value, ApiError> {
+        self.send(|| self.inner.post(path, body)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn rejections_by_status_or_envelope() {
+        let rejected = rejection(&Ok(json!({ "state": 401, "msg": "Not logged in" })));
+        assert_eq!(rejected.as_deref(), Some("session expired: Not logged in"));
+        assert!(rejection(&Ok(json!({ "state": 403 }))).is_some());
+        assert!(rejection(&Err(ApiError::SessionExpired("401 Unauthorized".into()))).is_some());
+    }
+
+    #[test]
+    fn other_responses_pass_through() {
+        assert!(rejection(&Ok(json!({ "state": 0, "data": [] }))).is_none());
+        assert!(rejection(&Ok(json!({ "state": 500 }))).is_none());
+        assert!(rejection(&Ok(json!([]))).is_none());
+        assert!(rejection(&Err(ApiError::NotFound(String::new()))).is_none());
+    }
+}
@@ -1,36 +1,51 @@
-use crate::{calendar::Calendar, gpa::*, round_score};
+use crate::{
+    calendar::Calendar,
+    client::{self, ApiError, SchoolisApi},
+    config::{Pattern, Rules},
+    gpa::*,
+    round_score,
+};
 use chrono::Duration;
 use itertools::Itertools;
-use serde::Deserialize;
-use std::collections::HashMap;
-
-pub async fn get_subject_ids(client: &reqwest::Client, semester_id: u64) -> Vec<u64> {
-    let response: serde_json::Value = client
-        .get(format!("https://tsinglanstudent.schoolis.cn/api/LearningTask/GetStuSubjectListForSelect?semesterId={semester_id}"))
-        .send()
-        .await
-        .unwrap()
-        .json()
-        .await
-        .unwrap();
-    let subjects = response["data"].as_array().expect("Failed to get subjects");
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt};
+
+#[derive(Deserialize)]
+struct SubjectListItem {
+    id: Option<u64>,
+}
+
+pub async fn get_subject_ids(
+    api: &dyn SchoolisApi,
+    semester_id: u64,
+) -> Result<Vec<u64>, ApiError> {
+    let subjects: Vec<SubjectListItem> = client::get(
+        api,
+        &format!("LearningTask/GetStuSubjectListForSelect?semesterId={semester_id}"),
+    )
+    .await?;
     let ids: Vec<u64> = subjects
         .iter()
-        .filter_map(|subject| subject["id"].as_u64())
+        .filter_map(|subject| subject.id)
         .unique()
         .collect();
-    ids
+    Ok(ids)
 }
 
-#[allow(dead_code)]
+#[derive(Clone)]
 pub struct Subject {
     pub subject_name: String,
     pub subject_id: u64,
     pub class_id: u64,
     pub total_score: f64,
     pub extra_credit: f64,
-    pub in_gpa: bool, // Unreliable method of determining whether subject counts into gpa
+    /// Whether the subject counts in the GPA, from the server unless a rule overrides it
+    pub in_gpa: bool,
+    /// Why the subject is left out of the GPA or forced into it
+    pub in_gpa_reason: Option<String>,
     pub evaluation_projects: Vec<EvaluationProject>,
+    /// Score mapping id from the server
+    pub score_mapping_id: Option<u64>,
     pub score_mapping_list_id: ScoreMappingId,
     pub score_mapping_list: Vec<ScoreMappingConfig>,
     pub gpa: f64,
@@ -42,16 +57,18 @@ pub struct Subject {
     pub weight: f64,
 }
 
+/// Fetches a subject, using the score mapping list its name suggests.
 pub async fn get_subject(
-    client: &reqwest::Client,
+    api: &dyn SchoolisApi,
     semester_id: u64,
     subject_id: u64,
     score_mapping_lists: &HashMap<ScoreMappingId, Vec<ScoreMappingConfig>>,
-) -> Subject {
-    let subject_detail = get_subject_detail(client, semester_id, subject_id).await;
-    let evaluation_projects = get_subject_evaluation_projects(client, &subject_detail).await;
+    rules: &Rules,
+) -> Result<Subject, ApiError> {
+    let subject_detail = get_subject_detail(api, semester_id, subject_id).await?;
+    let evaluation_projects = get_subject_evaluation_projects(api, &subject_detail).await?;
     let total_score = get_subject_score(&evaluation_projects);
-    let score_mapping_list_id = get_score_mapping_list_id(&subject_detail);
+    let score_mapping_list_id = rules.score_mapping(&subject_detail.subject_name);
     let score_mapping_list = score_mapping_lists[&score_mapping_list_id].clone();
     let gpa = gpa_from_score(total_score, &score_mapping_list);
     let max_gpa = gpa_from_score(100.0, &score_mapping_list);
@@ -62,14 +79,16 @@ pub async fn get_subject(
     let unweighted_max_gpa =
         gpa_from_score(100.0, &score_mapping_lists[&ScoreMappingId::NonWeighted]);
     let score_level = score_level_from_score(total_score, &score_mapping_list);
-    Subject {
+    Ok(Subject {
         subject_name: subject_detail.subject_name,
         subject_id,
         class_id: subject_detail.class_id,
         total_score,
         extra_credit: 0.0,
         in_gpa: true,
+        in_gpa_reason: None,
         evaluation_projects,
+        score_mapping_id: None,
         score_mapping_list_id,
         score_mapping_list,
         gpa,
@@ -79,6 +98,53 @@ pub async fn get_subject(
         score_level,
         elective: false,
         weight: 1.0,
+    })
+}
+
+impl Subject {
+    /// Updates the GPA and score level to match `total_score`.
+    pub fn update_gpa(
+        &mut self,
+        score_mapping_lists: &HashMap<ScoreMappingId, Vec<ScoreMappingConfig>>,
+    ) {
+        self.gpa = gpa_from_score(self.total_score, &self.score_mapping_list);
+        self.unweighted_gpa = gpa_from_score(
+            self.total_score,
+            &score_mapping_lists[&ScoreMappingId::NonWeighted],
+        );
+        self.score_level = score_level_from_score(self.total_score, &self.score_mapping_list);
+    }
+
+    /// Whether the subject counts in the calculated GPA.
+    pub fn counts_in_gpa(&self) -> bool {
+        self.in_gpa && !self.gpa.is_nan()
+    }
+
+    /// Why the subject doesn't count in the calculated GPA, if it doesn't.
+    pub fn exclusion_reason(&self) -> Option<String> {
+        if !self.in_gpa {
+            Some(
+                self.in_gpa_reason
+                    .clone()
+                    .unwrap_or_else(|| "not in the GPA".to_string()),
+            )
+        } else if self.gpa.is_nan() {
+            Some("no score".to_string())
+        } else {
+            None
+        }
+    }
+
+    /// Switches to another score mapping list and updates the GPA.
+    pub fn set_score_mapping(
+        &mut self,
+        score_mapping_list_id: ScoreMappingId,
+        score_mapping_lists: &HashMap<ScoreMappingId, Vec<ScoreMappingConfig>>,
+    ) {
+        self.score_mapping_list = score_mapping_lists[&score_mapping_list_id].clone();
+        self.score_mapping_list_id = score_mapping_list_id;
+        self.max_gpa = gpa_from_score(100.0, &self.score_mapping_list);
+        self.update_gpa(score_mapping_lists);
     }
 }
 
@@ -91,36 +157,42 @@ pub struct SubjectDetail {
     school_semester_id: u64,
 }
 
+#[derive(Deserialize)]
+struct TaskList {
+    list: Vec<TaskListItem>,
+}
+
+#[derive(Deserialize)]
+struct TaskListItem {
+    id: u64,
+}
+
 async fn get_subject_detail(
-    client: &reqwest::Client,
+    api: &dyn SchoolisApi,
     semester_id: u64,
     subject_id: u64,
-) -> SubjectDetail {
-    let response: serde_json::Value = client
-        .get(format!("https://tsinglanstudent.schoolis.cn/api/LearningTask/GetList?semesterId={semester_id}&subjectId={subject_id}&pageIndex=1&pageSize=1"))
-        .send()
-        .await.unwrap()
-        .json()
-        .await.unwrap();
-    let task_id = response["data"]["list"][0]["id"]
-        .as_u64()
-        .expect("Failed to get task id");
-    let response: serde_json::Value = client
-        .get(format!(
-            "https://tsinglanstudent.schoolis.cn/api/LearningTask/GetDetail?learningTaskId={task_id}",
-        ))
-        .send()
-        .await
-        .unwrap()
-        .json()
-        .await
-        .unwrap();
-    let subject_detail: SubjectDetail =
-        serde_json::from_value(response["data"].clone()).expect("Failed to get subject detail");
-    subject_detail
+) -> Result<SubjectDetail, ApiError> {
+    let task_list: TaskList = client::get(
+        api,
+        &format!("LearningTask/GetList?semesterId={semester_id}&subjectId={subject_id}&pageIndex=1&pageSize=1"),
+    )
+    .await?;
+    let task_id = match task_list.list.first() {
+        Some(task) => task.id,
+        None => {
+            return Err(ApiError::NotFound(format!(
+                "no learning tasks for subject {subject_id}"
+            )))
+        }
+    };
+    client::get(
+        api,
+        &format!("LearningTask/GetDetail?learningTaskId={task_id}"),
+    )
+    .await
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct EvaluationProject {
     pub evaluation_project_e_name: String,
@@ -134,9 +206,188 @@ pub struct EvaluationProject {
     pub evaluation_project_list: Vec<EvaluationProject>,
     #[serde(skip)]
     pub adjusted_proportion: f64,
+    /// How the tasks add up to the project score, `None` if no model or
+    /// several models reproduce the server's score
+    #[serde(skip)]
+    pub task_weighting: Option<TaskWeighting>,
 }
 
-#[derive(Deserialize)]
+/// How the teacher combines the tasks of an evaluation project.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(tag = "model", rename_all = "snake_case")]
+pub enum TaskWeighting {
+    /// Every task counts the same, the mean of their percentages
+    #[default]
+    EqualPercentage,
+    /// Sum of scores over sum of total scores
+    Points,
+    /// Equal percentages, ignoring the `n` lowest ones
+    DropLowest { n: usize },
+}
+
+impl fmt::Display for TaskWeighting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TaskWeighting::EqualPercentage => write!(f, "equal percentage"),
+            TaskWeighting::Points => write!(f, "points-based"),
+            TaskWeighting::DropLowest { n } => write!(f, "drop lowest {n}"),
+        }
+    }
+}
+
+/// Most tasks a drop-lowest weighting may leave out. With more, some `n`
+/// reproduces an integer score by coincidence in a project with many tasks.
+const MAX_DROPPED_TASKS: usize = 2;
+
+impl TaskWeighting {
+    /// The models worth trying for `scored_tasks` tasks with a score,
+    /// simplest first.
+    fn candidates(scored_tasks: usize) -> Vec<TaskWeighting> {
+        let mut candidates = vec![TaskWeighting::EqualPercentage, TaskWeighting::Points];
+        candidates.extend(
+            (1..scored_tasks)
+                .take(MAX_DROPPED_TASKS)
+                .map(|n| TaskWeighting::DropLowest { n }),
+        );
+        candidates
+    }
+
+    /// The share of the project each task makes up, in the order of `tasks`.
+    /// Tasks without a score are `None`, dropped tasks have a share of 0.
+    pub fn task_shares(&self, tasks: &[LearningTask]) -> Vec<Option<f64>> {
+        let scored: Vec<&LearningTask> = tasks.iter().filter(|task| task.score.is_some()).collect();
+        match self {
+            TaskWeighting::EqualPercentage => tasks
+                .iter()
+                .map(|task| task.score.map(|_| 1.0 / scored.len() as f64))
+                .collect(),
+            TaskWeighting::Points => {
+                let total: f64 = scored.iter().map(|task| task.total_score).sum();
+                tasks
+                    .iter()
+                    .map(|task| task.score.map(|_| task.total_score / total))
+                    .collect()
+            }
+            TaskWeighting::DropLowest { n } => {
+                let dropped: Vec<usize> = tasks
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(index, task)| {
+                        task.percentage().map(|percentage| (index, percentage))
+                    })
+                    .sorted_by(|(_, a), (_, b)| a.total_cmp(b))
+                    .take(*n)
+                    .map(|(index, _)| index)
+                    .collect();
+                let kept = scored.len().saturating_sub(*n);
+                tasks
+                    .iter()
+                    .enumerate()
+                    .map(|(index, task)| {
+                        task.score.map(|_| {
+                            if dropped.contains(&index) {
+                                0.0
+                            } else {
+                                1.0 / kept as f64
+                            }
+                        })
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// The project score the tasks add up to under this model.
+    pub fn score(&self, tasks: &[LearningTask]) -> Option<f64> {
+        let score: f64 = tasks
+            .iter()
+            .zip(self.task_shares(tasks))
+            .filter_map(|(task, share)| Some(task.percentage()? * share?))
+            .sum();
+        (tasks.iter().any(|task| task.score.is_some()) && score.is_finite()).then_some(score)
+    }
+}
+
+/// Whether `calculated` rounds to `score` at the precision the server
+/// reports `score` with.
+fn reproduces(calculated: f64, score: f64) -> bool {
+    let decimal_places = (0..2)
+        .find(|&decimal_places| round_score(score, decimal_places) == score)
+        .unwrap_or(2);
+    (round_score(calculated, decimal_places) - round_score(score, decimal_places)).abs() < 1e-9
+}
+
+impl EvaluationProject {
+    /// The proportion of the subject each task makes up, in the order of
+    /// `learning_task_and_exam_list`. Tasks without a score are `None`,
+    /// the others share the project's proportion according to the
+    /// task weighting, equally if it is unknown.
+    pub fn task_proportions(&self) -> Vec<Option<f64>> {
+        self.task_weighting
+            .unwrap_or_default()
+            .task_shares(&self.learning_task_and_exam_list)
+            .into_iter()
+            .map(|share| share.map(|share| share * self.adjusted_proportion))
+            .collect()
+    }
+
+    /// The project score implied by its tasks under the task weighting,
+    /// the mean of their percentages if it is unknown.
+    pub fn score_from_tasks(&self) -> Option<f64> {
+        self.task_weighting
+            .unwrap_or_default()
+            .score(&self.learning_task_and_exam_list)
+    }
+
+    /// The task weightings that reproduce the server's score, simplest first.
+    /// A weighting giving every task the same share as a simpler one is the
+    /// same model for these tasks and left out.
+    pub fn reproducing_task_weightings(&self) -> Vec<TaskWeighting> {
+        let tasks = &self.learning_task_and_exam_list;
+        let scored_tasks = tasks.iter().filter(|task| task.score.is_some()).count();
+        if self.score_is_null || scored_tasks == 0 {
+            return Vec::new();
+        }
+        let mut reproducing: Vec<(TaskWeighting, Vec<Option<f64>>)> = Vec::new();
+        for task_weighting in TaskWeighting::candidates(scored_tasks) {
+            if !task_weighting
+                .score(tasks)
+                .is_some_and(|score| reproduces(score, self.score))
+            {
+                continue;
+            }
+            let shares = task_weighting.task_shares(tasks);
+            let same_shares = |other: &Vec<Option<f64>>| {
+                shares.iter().zip(other).all(|(a, b)| match (a, b) {
+                    (Some(a), Some(b)) => (a - b).abs() < 1e-9,
+                    (a, b) => a.is_none() && b.is_none(),
+                })
+            };
+            if !reproducing.iter().any(|(_, other)| same_shares(other)) {
+                reproducing.push((task_weighting, shares));
+            }
+        }
+        reproducing
+            .into_iter()
+            .map(|(task_weighting, _)| task_weighting)
+            .collect()
+    }
+
+    /// Sets the task weighting to the one model that reproduces the server's
+    /// score, for this project and its sub-projects. If several models
+    /// reproduce it, which one the teacher used is ambiguous and it is `None`.
+    pub fn infer_task_weighting(&mut self) {
+        self.task_weighting = match self.reproducing_task_weightings()[..] {
+            [task_weighting] => Some(task_weighting),
+            _ => None,
+        };
+        for evaluation_project in &mut self.evaluation_project_list {
+            evaluation_project.infer_task_weighting();
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct LearningTask {
     pub name: String,
@@ -144,26 +395,47 @@ pub struct LearningTask {
     pub total_score: f64,
 }
 
+impl LearningTask {
+    pub fn percentage(&self) -> Option<f64> {
+        self.score.map(|score| score / self.total_score * 100.0)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DynamicScoreDetail {
+    evaluation_project_list: Vec<EvaluationProject>,
+}
+
 async fn get_subject_evaluation_projects(
-    client: &reqwest::Client,
+    api: &dyn SchoolisApi,
     subject_detail: &SubjectDetail,
-) -> Vec<EvaluationProject> {
-    let response: serde_json::Value = client
-        .get(format!("https://tsinglanstudent.schoolis.cn/api/DynamicScore/GetDynamicScoreDetail?classId={}&subjectId={}&semesterId={}",
-                     subject_detail.class_id, subject_detail.subject_id, subject_detail.school_semester_id))
-        .send()
-        .await.unwrap()
-        .json()
-        .await.unwrap();
-    let mut evaluation_projects: Vec<EvaluationProject> =
-        serde_json::from_value(response["data"]["evaluationProjectList"].clone())
-            .expect("Failed to get evaluation projects");
+) -> Result<Vec<EvaluationProject>, ApiError> {
+    let detail: DynamicScoreDetail = client::get(
+        api,
+        &format!(
+            "DynamicScore/GetDynamicScoreDetail?classId={}&subjectId={}&semesterId={}",
+            subject_detail.class_id, subject_detail.subject_id, subject_detail.school_semester_id
+        ),
+    )
+    .await?;
+    let mut evaluation_projects = detail.evaluation_project_list;
+    adjust_proportions(&mut evaluation_projects);
+    for evaluation_project in &mut evaluation_projects {
+        evaluation_project.infer_task_weighting();
+    }
+    Ok(evaluation_projects)
+}
+
+/// Renormalizes the proportions of the projects with a score, so that they
+/// add up to 100% of the subject.
+pub fn adjust_proportions(evaluation_projects: &mut [EvaluationProject]) {
     let total_proportion: f64 = evaluation_projects
         .iter()
         .filter(|evaluation_project| !evaluation_project.score_is_null)
         .map(|evaluation_project| evaluation_project.proportion)
         .sum();
-    for evaluation_project in &mut evaluation_projects {
+    for evaluation_project in evaluation_projects {
         evaluation_project.adjusted_proportion =
             evaluation_project.proportion / total_proportion * 100.0;
         let total_proportion: f64 = evaluation_project
@@ -178,10 +450,9 @@ async fn get_subject_evaluation_projects(
                 * evaluation_project.adjusted_proportion;
         }
     }
-    evaluation_projects
 }
 
-fn get_subject_score(evaluation_projects: &[EvaluationProject]) -> f64 {
+pub fn get_subject_score(evaluation_projects: &[EvaluationProject]) -> f64 {
     evaluation_projects
         .iter()
         .filter(|evaluation_project| !evaluation_project.score_is_null)
@@ -192,27 +463,45 @@ fn get_subject_score(evaluation_projects: &[EvaluationProject]) -> f64 {
         .unwrap_or(f64::NAN)
 }
 
-pub async fn get_elective_class_ids(client: &reqwest::Client) -> Vec<u64> {
-    let current_time = chrono::Utc::now();
+pub async fn get_elective_class_ids(
+    api: &dyn SchoolisApi,
+    elective_pattern: &Pattern,
+) -> Result<Vec<u64>, ApiError> {
+    let today = chrono::Utc::now().date_naive();
     // 8 days = 6 days per cycle + 2 weekends
-    let begin_time = current_time - Duration::days(8);
-    let end_time = current_time + Duration::days(8);
-    let calendar = Calendar::new(client, begin_time, end_time, false).await;
+    let begin_date = today - Duration::days(8);
+    let end_date = today + Duration::days(8);
+    let calendar = Calendar::new(api, begin_date, end_date, false).await?;
     let elective_class_ids = calendar
         .blocks
         .iter()
-        .filter(|block| block.class_name.contains("Ele"))
+        .filter(|block| elective_pattern.is_match(&block.class_name))
         .map(|block| block.id)
         .unique()
         .collect();
-    elective_class_ids
+    Ok(elective_class_ids)
 }
 
-pub fn adjust_weights(subject: &mut Subject, elective_class_ids: &[u64]) {
-    let elective = elective_class_ids.contains(&subject.class_id);
-    if elective || subject.subject_name == "C-Humanities" {
-        subject.elective = elective;
-        subject.weight = 0.5;
+pub fn adjust_weights(subject: &mut Subject, elective_class_ids: &[u64], rules: &Rules) {
+    subject.elective = elective_class_ids.contains(&subject.class_id);
+    if subject.elective {
+        subject.weight = rules.elective_weight;
+    }
+    if let Some(weight) = rules.weight(&subject.subject_name) {
+        subject.weight = weight;
+    }
+}
+
+/// Forces the subject into or out of the GPA if a rule says so.
+pub fn apply_gpa_override(subject: &mut Subject, rules: &Rules) {
+    if let Some((in_gpa, reason)) = rules.gpa_override(&subject.subject_name) {
+        let reason = if in_gpa {
+            format!("included by {reason}")
+        } else {
+            format!("excluded by {reason}")
+        };
+        subject.in_gpa = in_gpa;
+        subject.in_gpa_reason = Some(reason);
     }
 }
 
@@ -230,18 +519,22 @@ pub struct SubjectDynamicScore {
     subject_total_score: f64,
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SemesterDynamicScore {
+    student_semester_dynamic_score_basic_dtos: Vec<SubjectDynamicScore>,
+}
+
 pub async fn get_subject_dynamic_scores(
-    client: &reqwest::Client,
+    api: &dyn SchoolisApi,
     semester_id: u64,
-) -> Vec<SubjectDynamicScore> {
-    let response: serde_json::Value = client
-        .get(format!("https://tsinglanstudent.schoolis.cn/api/DynamicScore/GetStuSemesterDynamicScore?semesterId={semester_id}"))
-        .send()
-        .await.unwrap()
-        .json()
-        .await.unwrap();
-    serde_json::from_value(response["data"]["studentSemesterDynamicScoreBasicDtos"].clone())
-        .expect("Failed to get semester dynamic score")
+) -> Result<Vec<SubjectDynamicScore>, ApiError> {
+    let semester_dynamic_score: SemesterDynamicScore = client::get(
+        api,
+        &format!("DynamicScore/GetStuSemesterDynamicScore?semesterId={semester_id}"),
+    )
+    .await?;
+    Ok(semester_dynamic_score.student_semester_dynamic_score_basic_dtos)
 }
 
 pub fn overlay_subject(
@@ -252,22 +545,179 @@ pub fn overlay_subject(
     for dynamic_score in subject_dynamic_scores {
         if subject.class_id == dynamic_score.class_id {
             subject.in_gpa = dynamic_score.is_in_grade;
+            if !subject.in_gpa {
+                subject.in_gpa_reason = Some("left out by the school".to_string());
+            }
+            subject.score_mapping_id = Some(dynamic_score.score_mapping_id);
             let new_score = dynamic_score.subject_score.unwrap_or(f64::NAN)
                 / dynamic_score.subject_total_score
                 * 100.0;
             subject.extra_credit = new_score - round_score(subject.total_score, 1);
             subject.total_score = new_score;
-            // Definitely need to refactor this spaghetti
-            subject.gpa = gpa_from_score(subject.total_score, &subject.score_mapping_list);
-            subject.unweighted_gpa = gpa_from_score(
-                subject.total_score,
-                &score_mapping_lists[&ScoreMappingId::NonWeighted],
-            );
-            subject.score_level =
-                score_level_from_score(subject.total_score, &subject.score_mapping_list);
+            subject.update_gpa(score_mapping_lists);
 
             // There should be only one match
             return;
         }
     }
 }
+
+#[cfg(test)]
+impl Subject {
+    /// A non-weighted subject scored like a fetched one from its evaluation
+    /// projects.
+    pub fn for_tests(name: &str, mut evaluation_projects: Vec<EvaluationProject>) -> Subject {
+        let score_mapping_lists = default_score_mapping_lists();
+        adjust_proportions(&mut evaluation_projects);
+        for evaluation_project in &mut evaluation_projects {
+            evaluation_project.infer_task_weighting();
+        }
+        let score_mapping_list = score_mapping_lists[&ScoreMappingId::NonWeighted].clone();
+        let mut subject = Subject {
+            subject_name: name.to_string(),
+            subject_id: 1,
+            class_id: 1,
+            total_score: get_subject_score(&evaluation_projects),
+            extra_credit: 0.0,
+            in_gpa: true,
+            in_gpa_reason: None,
+            evaluation_projects,
+            score_mapping_id: None,
+            score_mapping_list_id: ScoreMappingId::NonWeighted,
+            max_gpa: gpa_from_score(100.0, &score_mapping_list),
+            unweighted_max_gpa: gpa_from_score(100.0, &score_mapping_list),
+            score_mapping_list,
+            gpa: f64::NAN,
+            unweighted_gpa: f64::NAN,
+            score_level: String::new(),
+            elective: false,
+            weight: 1.0,
+        };
+        subject.update_gpa(&score_mapping_lists);
+        subject
+    }
+}
+
+#[cfg(test)]
+impl EvaluationProject {
+    /// A project without tasks as the server sends it, scored `score`,
+    /// or not released yet if `None`.
+    pub fn for_tests(name: &str, proportion: f64, score: Option<f64>) -> EvaluationProject {
+        EvaluationProject {
+            evaluation_project_e_name: name.to_string(),
+            proportion,
+            score: score.unwrap_or(0.0),
+            score_level: String::new(),
+            gpa: 0.0,
+            score_is_null: score.is_none(),
+            learning_task_and_exam_list: Vec::new(),
+            evaluation_project_list: Vec::new(),
+            adjusted_proportion: 0.0,
+            task_weighting: None,
+        }
+    }
+
+    pub fn with_gpa(mut self, gpa: f64) -> EvaluationProject {
+        self.gpa = gpa;
+        self
+    }
+
+    pub fn with_task(mut self, name: &str, score: f64, total_score: f64) -> EvaluationProject {
+        self.learning_task_and_exam_list.push(LearningTask {
+            name: name.to_string(),
+            score: Some(score),
+            total_score,
+        });
+        self
+    }
+
+    pub fn with_sub_project(mut self, sub_project: EvaluationProject) -> EvaluationProject {
+        self.evaluation_project_list.push(sub_project);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A project the server scored `score` from `tasks` of (score, total).
+    fn project(score: f64, tasks: &[(f64, f64)]) -> EvaluationProject {
+        let mut evaluation_project = EvaluationProject::for_tests("Formative", 100.0, Some(score));
+        for (index, &(score, total_score)) in tasks.iter().enumerate() {
+            evaluation_project =
+                evaluation_project.with_task(&format!("Task {index}"), score, total_score);
+        }
+        evaluation_project.infer_task_weighting();
+        evaluation_project
+    }
+
+    #[test]
+    fn infers_equal_percentages() {
+        let evaluation_project = project(70.0, &[(5.0, 10.0), (90.0, 100.0)]);
+        assert_eq!(
+            evaluation_project.task_weighting,
+            Some(TaskWeighting::EqualPercentage)
+        );
+    }
+
+    #[test]
+    fn infers_points_from_total_scores() {
+        // 95 / 110 points, where the mean of 50% and 90% would be 70
+        let evaluation_project = project(86.36, &[(5.0, 10.0), (90.0, 100.0)]);
+        assert_eq!(
+            evaluation_project.task_weighting,
+            Some(TaskWeighting::Points)
+        );
+    }
+
+    #[test]
+    fn infers_dropped_tasks() {
+        let evaluation_project = project(95.0, &[(50.0, 100.0), (90.0, 100.0), (100.0, 100.0)]);
+        assert_eq!(
+            evaluation_project.task_weighting,
+            Some(TaskWeighting::DropLowest { n: 1 })
+        );
+    }
+
+    #[test]
+    fn equivalent_weightings_are_not_ambiguous() {
+        // Equal totals make points the same model as equal percentages
+        let evaluation_project = project(80.0, &[(70.0, 100.0), (90.0, 100.0)]);
+        assert_eq!(
+            evaluation_project.reproducing_task_weightings(),
+            [TaskWeighting::EqualPercentage]
+        );
+        assert_eq!(
+            evaluation_project.task_weighting,
+            Some(TaskWeighting::EqualPercentage)
+        );
+    }
+
+    #[test]
+    fn several_reproducing_weightings_are_ambiguous() {
+        // 182 / 210 points is 86.67, dropping the 80% leaves a mean of 87
+        let evaluation_project = project(87.0, &[(8.0, 10.0), (90.0, 100.0), (84.0, 100.0)]);
+        assert_eq!(
+            evaluation_project.reproducing_task_weightings(),
+            [TaskWeighting::Points, TaskWeighting::DropLowest { n: 1 }]
+        );
+        assert_eq!(evaluation_project.task_weighting, None);
+    }
+
+    #[test]
+    fn drops_at_most_two_tasks() {
+        // Only dropping the three zeros reproduces it
+        let tasks = [0.0, 0.0, 0.0, 100.0, 100.0].map(|score| (score, 100.0));
+        let evaluation_project = project(100.0, &tasks);
+        assert!(evaluation_project.reproducing_task_weightings().is_empty());
+    }
+
+    #[test]
+    fn no_weighting_reproduces_hidden_tasks() {
+        let evaluation_project = project(60.0, &[(5.0, 10.0), (90.0, 100.0)]);
+        assert_eq!(evaluation_project.task_weighting, None);
+        // The plain mean is still what the tasks imply
+        assert_eq!(evaluation_project.score_from_tasks(), Some(70.0));
+    }
+}
@@ -0,0 +1,417 @@
+use crate::{
+    colorize,
+    config::Config,
+    gpa::*,
+    round_score,
+    subject::{EvaluationProject, Subject},
+};
+use tabled::{
+    builder::Builder,
+    settings::{object::Rows, Remove, Style},
+    Table,
+};
+
+/// What a subject needs on its remaining evaluation projects, i.e. the ones
+/// with `score_is_null`, to end with a given score. Unreleased sub-projects
+/// of released projects count as remaining too.
+pub struct Remaining<'a> {
+    subject: &'a Subject,
+    /// Released scores times their proportion of the subject
+    known_points: f64,
+    known_proportion: f64,
+    remaining_proportion: f64,
+    remaining_projects: Vec<String>,
+}
+
+/// The average needed on the remaining work for a grade.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Requirement {
+    Secured,
+    Needs(f64),
+    Unreachable,
+}
+
+impl<'a> Remaining<'a> {
+    pub fn new(subject: &'a Subject) -> Remaining<'a> {
+        let mut remaining = Remaining {
+            subject,
+            known_points: 0.0,
+            known_proportion: 0.0,
+            remaining_proportion: 0.0,
+            remaining_projects: Vec::new(),
+        };
+        remaining.add_projects(&subject.evaluation_projects, 100.0, "");
+        remaining
+    }
+
+    /// Adds `evaluation_projects`, which together make up `share` percent
+    /// of the subject, recursing into released projects with unreleased
+    /// sub-projects.
+    fn add_projects(&mut self, evaluation_projects: &[EvaluationProject], share: f64, path: &str) {
+        let total_proportion: f64 = evaluation_projects
+            .iter()
+            .map(|evaluation_project| evaluation_project.proportion)
+            .sum();
+        if total_proportion == 0.0 {
+            return;
+        }
+        for evaluation_project in evaluation_projects {
+            let proportion = evaluation_project.proportion / total_proportion * share;
+            let name = &evaluation_project.evaluation_project_e_name;
+            let name = if path.is_empty() {
+                name.clone()
+            } else {
+                format!("{path} > {name}")
+            };
+            if evaluation_project.score_is_null {
+                self.remaining_proportion += proportion;
+                self.remaining_projects.push(name);
+            } else if evaluation_project
+                .evaluation_project_list
+                .iter()
+                .any(|sub_evaluation_project| sub_evaluation_project.score_is_null)
+            {
+                self.add_projects(
+                    &evaluation_project.evaluation_project_list,
+                    proportion,
+                    &name,
+                );
+            } else {
+                self.known_points += evaluation_project.score * proportion;
+                self.known_proportion += proportion;
+            }
+        }
+    }
+
+    /// The average of the released work.
+    fn known_score(&self) -> f64 {
+        if self.known_proportion == 0.0 {
+            0.0
+        } else {
+            self.known_points / self.known_proportion
+        }
+    }
+
+    fn extra_credit(&self) -> f64 {
+        if self.subject.extra_credit.is_finite() {
+            self.subject.extra_credit
+        } else {
+            0.0
+        }
+    }
+
+    /// The share of the subject that is still to come, from 0 to 1.
+    fn remaining_fraction(&self) -> f64 {
+        self.remaining_proportion / (self.known_proportion + self.remaining_proportion)
+    }
+
+    /// The average needed on the remaining work for the subject to end
+    /// with at least `min_value`.
+    pub fn requirement(&self, min_value: f64) -> Requirement {
+        // Scores are rounded to one decimal place before being mapped
+        let min_value = min_value - 0.05 - self.extra_credit();
+        if self.remaining_proportion == 0.0 {
+            return if self.known_score() >= min_value {
+                Requirement::Secured
+            } else {
+                Requirement::Unreachable
+            };
+        }
+        let total_proportion = self.known_proportion + self.remaining_proportion;
+        let average =
+            (min_value * total_proportion - self.known_points) / self.remaining_proportion;
+        if average <= 0.0 {
+            Requirement::Secured
+        } else if average > 100.0 {
+            Requirement::Unreachable
+        } else {
+            Requirement::Needs(average)
+        }
+    }
+
+    /// The average the remaining work is assumed to get by default,
+    /// that of the released work.
+    fn pace(&self) -> f64 {
+        self.known_score()
+    }
+}
+
+pub fn print_requirements(subjects: &[Subject], config: &Config) {
+    let Some(grades) = subjects
+        .iter()
+        .map(|subject| &subject.score_mapping_list)
+        .max_by_key(|score_mapping_list| score_mapping_list.len())
+    else {
+        println!("No subjects this semester");
+        return;
+    };
+    let mut builder = Builder::default();
+    let mut header = vec!["Subject".to_string(), "Remaining".to_string()];
+    header.extend(grades.iter().map(|grade| grade.display_name.clone()));
+    builder.push_record(header);
+    for subject in subjects {
+        let remaining = Remaining::new(subject);
+        let mut record = vec![
+            colorize(&subject.subject_name, &subject.score_level, &config.colors),
+            if remaining.remaining_projects.is_empty() {
+                "-".to_string()
+            } else {
+                remaining.remaining_projects.join(", ")
+            },
+        ];
+        for grade in grades {
+            let cell = match subject
+                .score_mapping_list
+                .iter()
+                .find(|config| config.display_name == grade.display_name)
+            {
+                Some(config) => match remaining.requirement(config.min_value) {
+                    Requirement::Secured => "✓".to_string(),
+                    Requirement::Unreachable => "✗".to_string(),
+                    Requirement::Needs(average) => format!("{}", round_score(average, 1)),
+                },
+                None => String::new(),
+            };
+            record.push(cell);
+        }
+        builder.push_record(record);
+    }
+    let table = builder.build().with(Style::rounded()).to_string();
+    println!("{table}");
+    println!("Average needed on each remaining project, ✓ already secured, ✗ unreachable");
+}
+
+/// A grade a subject can end with, and what it costs.
+struct GradeOption {
+    grade: String,
+    gpa: f64,
+    requirement: Requirement,
+    /// Points the subject's score has to end above where the current pace
+    /// leads, i.e. the average needed above the pace times the share of the
+    /// subject that remains
+    cost: f64,
+}
+
+/// Finds the cheapest grades, in total points above where each subject's
+/// current pace leads, that reach `target` weighted GPA. Returns `None` if
+/// the target is unreachable.
+fn solve_target_gpa(subjects: &[&Subject], target: f64) -> Option<Vec<GradeOption>> {
+    // Weighted GPAs are summed in hundredths, which is exact for the usual
+    // GPAs in tenths and weights in halves, and keeps the table small.
+    let to_units = |gpa: f64, weight: f64| (gpa * weight * 100.0).round() as usize;
+    let total_weight: f64 = subjects.iter().map(|subject| subject.weight).sum();
+    let target_units = (target * total_weight * 100.0 - 1e-6).ceil().max(0.0) as usize;
+
+    let options: Vec<Vec<GradeOption>> = subjects
+        .iter()
+        .map(|subject| subject_options(subject))
+        .collect();
+    let max_units: usize = subjects
+        .iter()
+        .zip(&options)
+        .map(|(subject, options)| {
+            options
+                .iter()
+                .map(|option| to_units(option.gpa, subject.weight))
+                .max()
+                .unwrap_or(0)
+        })
+        .sum();
+    if max_units < target_units {
+        return None;
+    }
+
+    // cost[i][units]: cheapest cost for the first i subjects to sum to units,
+    // with the option chosen for subject i - 1 to get there
+    let mut cost = vec![vec![(f64::INFINITY, 0); max_units + 1]; subjects.len() + 1];
+    cost[0][0] = (0.0, 0);
+    for (i, (subject, options)) in subjects.iter().zip(&options).enumerate() {
+        for units in 0..=max_units {
+            let (previous_cost, _) = cost[i][units];
+            if previous_cost.is_infinite() {
+                continue;
+            }
+            for (option_index, option) in options.iter().enumerate() {
+                let next_units = units + to_units(option.gpa, subject.weight);
+                let next_cost = previous_cost + option.cost;
+                if next_units <= max_units && next_cost < cost[i + 1][next_units].0 {
+                    cost[i + 1][next_units] = (next_cost, option_index);
+                }
+            }
+        }
+    }
+
+    let (mut units, _) = cost[subjects.len()]
+        .iter()
+        .enumerate()
+        .skip(target_units)
+        .filter(|(_, (cost, _))| cost.is_finite())
+        .min_by(|(_, (a, _)), (_, (b, _))| a.total_cmp(b))?;
+    let mut chosen = Vec::new();
+    let mut options = options;
+    for i in (0..subjects.len()).rev() {
+        let option_index = cost[i + 1][units].1;
+        let option = options[i].swap_remove(option_index);
+        units -= to_units(option.gpa, subjects[i].weight);
+        chosen.push(option);
+    }
+    chosen.reverse();
+    Some(chosen)
+}
+
+fn subject_options(subject: &Subject) -> Vec<GradeOption> {
+    let remaining = Remaining::new(subject);
+    let pace = remaining.pace();
+    let current = GradeOption {
+        grade: subject.score_level.clone(),
+        gpa: subject.gpa,
+        requirement: Requirement::Secured,
+        cost: 0.0,
+    };
+    let mut options = vec![];
+    for config in &subject.score_mapping_list {
+        if config.gpa <= subject.gpa {
+            continue;
+        }
+        if let Requirement::Needs(average) = remaining.requirement(config.min_value) {
+            options.push(GradeOption {
+                grade: config.display_name.clone(),
+                gpa: config.gpa,
+                requirement: Requirement::Needs(average),
+                cost: (average - pace).max(0.0) * remaining.remaining_fraction(),
+            });
+        }
+    }
+    options.push(current);
+    options
+}
+
+pub fn print_target_gpa(subjects: &[Subject], target: f64, config: &Config) {
+    let calculated_gpa = calculate_gpa(subjects).weighted_gpa;
+    let subjects: Vec<&Subject> = subjects
+        .iter()
+        .filter(|subject| subject.counts_in_gpa())
+        .collect();
+    if subjects.is_empty() {
+        println!("No subjects count towards the GPA yet");
+        return;
+    }
+    if calculated_gpa >= target {
+        println!("GPA {target} is already secured at the current pace ({calculated_gpa:.2})");
+        return;
+    }
+    let Some(chosen) = solve_target_gpa(&subjects, target) else {
+        println!("GPA {target} is unreachable");
+        return;
+    };
+    let mut data = vec![(
+        "Subject".to_string(),
+        "Grade".to_string(),
+        "Needed on remaining work".to_string(),
+    )];
+    for (subject, option) in subjects.iter().zip(&chosen) {
+        let needed = match option.requirement {
+            Requirement::Needs(average) => format!(
+                "{}% on {}",
+                round_score(average, 1),
+                Remaining::new(subject).remaining_projects.join(", ")
+            ),
+            _ => "current pace".to_string(),
+        };
+        let grade = if option.grade == subject.score_level {
+            option.grade.clone()
+        } else {
+            format!("{} -> {}", subject.score_level, option.grade)
+        };
+        data.push((
+            colorize(&subject.subject_name, &option.grade, &config.colors),
+            grade,
+            needed,
+        ));
+    }
+    let table = Table::new(data)
+        .with(Remove::row(Rows::first()))
+        .with(Style::rounded())
+        .to_string();
+    println!("Cheapest way to reach GPA {target}:");
+    println!("{table}");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A subject with `known` on the released part and `remaining` percent to come.
+    fn subject(name: &str, known: f64, remaining: f64) -> Subject {
+        Subject::for_tests(
+            name,
+            vec![
+                EvaluationProject::for_tests("Summative", 100.0 - remaining, Some(known)),
+                EvaluationProject::for_tests("Final Exam", remaining, None),
+            ],
+        )
+    }
+
+    fn assert_needs(requirement: Requirement, expected: f64) {
+        match requirement {
+            Requirement::Needs(average) => assert!((average - expected).abs() < 1e-9, "{average}"),
+            other => panic!("expected Needs({expected}), got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn requirement_of_remaining_projects() {
+        let subject = subject("English", 90.0, 40.0);
+        let remaining = Remaining::new(&subject);
+        assert_eq!(remaining.remaining_projects, ["Final Exam"]);
+        assert_needs(remaining.requirement(93.0), 97.375);
+        assert_eq!(remaining.requirement(97.0), Requirement::Unreachable);
+        assert_eq!(remaining.requirement(0.0), Requirement::Secured);
+    }
+
+    #[test]
+    fn requirement_without_remaining_work() {
+        let subject = Subject::for_tests(
+            "English",
+            vec![EvaluationProject::for_tests("Summative", 100.0, Some(90.0))],
+        );
+        let remaining = Remaining::new(&subject);
+        assert_eq!(remaining.requirement(90.0), Requirement::Secured);
+        assert_eq!(remaining.requirement(93.0), Requirement::Unreachable);
+    }
+
+    #[test]
+    fn requirement_counts_unreleased_sub_projects() {
+        let subject = Subject::for_tests(
+            "Physics",
+            vec![EvaluationProject::for_tests("Summative", 100.0, Some(80.0))
+                .with_sub_project(EvaluationProject::for_tests("Tests", 50.0, Some(80.0)))
+                .with_sub_project(EvaluationProject::for_tests("Quizzes", 50.0, None))],
+        );
+        let remaining = Remaining::new(&subject);
+        assert_eq!(remaining.remaining_projects, ["Summative > Quizzes"]);
+        assert!((remaining.remaining_fraction() - 0.5).abs() < 1e-9);
+        assert_needs(remaining.requirement(90.0), 99.9);
+    }
+
+    #[test]
+    fn target_gpa_raises_the_subject_with_the_least_left_to_gain() {
+        // Big needs 89.9 on half the subject, Small 94.75 on a fifth of it.
+        // Small ends fewer points above its pace, so it is the cheaper one.
+        let big = subject("Big", 84.0, 50.0);
+        let small = subject("Small", 85.0, 20.0);
+        let chosen = solve_target_gpa(&[&big, &small], 3.15).unwrap();
+        assert_eq!(chosen[0].grade, "B");
+        assert_eq!(chosen[0].cost, 0.0);
+        assert_eq!(chosen[1].grade, "B+");
+        assert_needs(chosen[1].requirement, 94.75);
+        assert!((chosen[1].cost - 1.95).abs() < 1e-9);
+    }
+
+    #[test]
+    fn unreachable_target_gpa() {
+        let big = subject("Big", 84.0, 50.0);
+        let small = subject("Small", 85.0, 20.0);
+        assert!(solve_target_gpa(&[&big, &small], 4.3).is_none());
+    }
+}
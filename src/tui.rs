@@ -0,0 +1,475 @@
+use crate::{
+    client::SchoolisApi,
+    config::{ColorScheme, Config},
+    error::Error,
+    fetch_report,
+    gpa::{calculate_gpa, ScoreMappingConfig, ScoreMappingId},
+    round_score,
+    semester::Semester,
+    subject::{EvaluationProject, Subject},
+    update_history,
+};
+use log::LevelFilter;
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Modifier, Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Clear, List, ListItem, ListState, Paragraph, Row, Table, TableState},
+    DefaultTerminal, Frame,
+};
+use std::{collections::HashMap, io::IsTerminal, str::FromStr, sync::Arc};
+
+#[derive(PartialEq)]
+enum Focus {
+    Subjects,
+    Detail,
+    Semesters,
+}
+
+struct App<'a> {
+    client: &'a Arc<dyn SchoolisApi>,
+    /// Bypasses the cache, for refreshing
+    fresh_client: &'a Arc<dyn SchoolisApi>,
+    score_mapping_lists: &'a Arc<HashMap<ScoreMappingId, Vec<ScoreMappingConfig>>>,
+    config: &'a Config,
+    /// Newest first, the way the semester switcher lists them
+    semesters: Vec<Semester>,
+    semester: usize,
+    subjects: Vec<Subject>,
+    gpa: f64,
+    tasks: bool,
+    focus: Focus,
+    subject_list: ListState,
+    detail: TableState,
+    semester_list: ListState,
+    status: String,
+}
+
+/// Interactive grade browser, opened by `tls-xb tui`.
+pub async fn run(
+    client: &Arc<dyn SchoolisApi>,
+    fresh_client: &Arc<dyn SchoolisApi>,
+    semesters: &[Semester],
+    semester: &Semester,
+    score_mapping_lists: &Arc<HashMap<ScoreMappingId, Vec<ScoreMappingConfig>>>,
+    config: &Config,
+    tasks: bool,
+) -> Result<(), Error> {
+    if !std::io::stdout().is_terminal() {
+        return Err(Error::input("tls-xb tui needs a terminal"));
+    }
+    let mut semesters = semesters.to_vec();
+    semesters.sort_by_key(|semester| std::cmp::Reverse(semester.start_date));
+    let mut app = App {
+        client,
+        fresh_client,
+        score_mapping_lists,
+        config,
+        semester: semesters
+            .iter()
+            .position(|other| other.id == semester.id)
+            .unwrap_or_default(),
+        semesters,
+        subjects: Vec::new(),
+        gpa: f64::NAN,
+        tasks,
+        focus: Focus::Subjects,
+        subject_list: ListState::default(),
+        detail: TableState::default(),
+        semester_list: ListState::default(),
+        status: String::new(),
+    };
+
+    // Log lines would be drawn over the interface
+    let log_level = log::max_level();
+    log::set_max_level(LevelFilter::Off);
+    let mut terminal = ratatui::init();
+    let result = app.run(&mut terminal).await;
+    ratatui::restore();
+    log::set_max_level(log_level);
+    result
+}
+
+impl App<'_> {
+    async fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<(), Error> {
+        self.load(terminal, false).await?;
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match (&self.focus, key.code) {
+                (Focus::Semesters, KeyCode::Esc | KeyCode::Char('s')) => {
+                    self.focus = Focus::Subjects;
+                }
+                (Focus::Semesters, KeyCode::Up | KeyCode::Char('k')) => {
+                    self.semester_list.select_previous();
+                }
+                (Focus::Semesters, KeyCode::Down | KeyCode::Char('j')) => {
+                    self.semester_list.select_next();
+                }
+                (Focus::Semesters, KeyCode::Enter) => {
+                    self.focus = Focus::Subjects;
+                    if let Some(semester) = self.semester_list.selected() {
+                        self.semester = semester.min(self.semesters.len() - 1);
+                        self.load(terminal, false).await?;
+                    }
+                }
+                (Focus::Semesters, _) => {}
+                (_, KeyCode::Char('q')) => return Ok(()),
+                (Focus::Detail, KeyCode::Esc | KeyCode::Left | KeyCode::Char('h')) => {
+                    self.focus = Focus::Subjects;
+                }
+                (Focus::Subjects, KeyCode::Esc) => return Ok(()),
+                (Focus::Subjects, KeyCode::Enter | KeyCode::Right | KeyCode::Char('l')) => {
+                    self.focus = Focus::Detail;
+                    self.detail.select(Some(0));
+                }
+                (Focus::Subjects, KeyCode::Up | KeyCode::Char('k')) => {
+                    self.subject_list.select_previous();
+                    self.detail = TableState::default();
+                }
+                (Focus::Subjects, KeyCode::Down | KeyCode::Char('j')) => {
+                    self.subject_list.select_next();
+                    self.detail = TableState::default();
+                }
+                (Focus::Detail, KeyCode::Up | KeyCode::Char('k')) => self.detail.select_previous(),
+                (Focus::Detail, KeyCode::Down | KeyCode::Char('j')) => self.detail.select_next(),
+                (_, KeyCode::Char('t')) => self.tasks = !self.tasks,
+                (_, KeyCode::Char('r')) => self.load(terminal, true).await?,
+                (_, KeyCode::Char('s')) => {
+                    self.focus = Focus::Semesters;
+                    self.semester_list.select(Some(self.semester));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Fetches the selected semester, bypassing the cache if `refresh` is set.
+    /// Failures are shown in the footer, so a flaky connection doesn't end
+    /// the session.
+    async fn load(&mut self, terminal: &mut DefaultTerminal, refresh: bool) -> Result<(), Error> {
+        self.status = "Loading...".to_string();
+        terminal.draw(|frame| self.draw(frame))?;
+        let semester = &self.semesters[self.semester];
+        let client = if refresh {
+            self.fresh_client
+        } else {
+            self.client
+        };
+        match fetch_report(
+            client,
+            semester,
+            self.score_mapping_lists,
+            &self.config.rules,
+        )
+        .await
+        {
+            Ok((subjects, gpa, report)) => {
+                self.subjects = subjects;
+                self.gpa = gpa;
+                self.status.clear();
+                // Nothing to show the changes in, the next report will
+                if self.config.history {
+                    if let Err(err) = update_history(&report) {
+                        self.status = format!("Failed to update grade history: {err}");
+                    }
+                }
+                if self
+                    .subject_list
+                    .selected()
+                    .is_none_or(|selected| selected >= self.subjects.len())
+                {
+                    self.subject_list
+                        .select((!self.subjects.is_empty()).then_some(0));
+                }
+                self.detail = TableState::default();
+                if self.focus == Focus::Detail {
+                    self.detail.select(Some(0));
+                }
+            }
+            Err(err) => self.status = format!("Refresh failed: {err}"),
+        }
+        Ok(())
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, footer] =
+            Layout::vertical([Constraint::Min(0), Constraint::Length(3)]).areas(frame.area());
+        let [subjects, detail] =
+            Layout::horizontal([Constraint::Percentage(35), Constraint::Percentage(65)])
+                .areas(main);
+        self.draw_subjects(frame, subjects);
+        self.draw_detail(frame, detail);
+        self.draw_footer(frame, footer);
+        if self.focus == Focus::Semesters {
+            self.draw_semesters(frame, main);
+        }
+    }
+
+    fn semester_name(semester: &Semester) -> String {
+        format!(
+            "{}, Semester {}",
+            semester.academic_year(),
+            semester.semester
+        )
+    }
+
+    fn border_style(&self, focus: Focus) -> Style {
+        if self.focus == focus {
+            Style::new().cyan()
+        } else {
+            Style::new()
+        }
+    }
+
+    fn draw_subjects(&mut self, frame: &mut Frame, area: Rect) {
+        let items: Vec<ListItem> = self
+            .subjects
+            .iter()
+            .map(|subject| {
+                let score = if subject.total_score.is_nan() {
+                    "-".to_string()
+                } else {
+                    format!(
+                        "{} {} {}",
+                        round_score(subject.total_score, 1),
+                        subject.score_level,
+                        subject.gpa
+                    )
+                };
+                let line = Line::from(vec![
+                    Span::raw(subject.subject_name.clone()),
+                    Span::raw("  "),
+                    Span::raw(score),
+                ]);
+                if subject.in_gpa {
+                    ListItem::new(line)
+                        .style(level_style(&subject.score_level, &self.config.colors))
+                } else {
+                    ListItem::new(line).dark_gray()
+                }
+            })
+            .collect();
+        let title = Self::semester_name(&self.semesters[self.semester]);
+        let list = List::new(items)
+            .block(
+                Block::bordered()
+                    .title(title)
+                    .border_style(self.border_style(Focus::Subjects)),
+            )
+            .highlight_style(Modifier::REVERSED);
+        frame.render_stateful_widget(list, area, &mut self.subject_list);
+    }
+
+    fn draw_detail(&mut self, frame: &mut Frame, area: Rect) {
+        let block = Block::bordered().border_style(self.border_style(Focus::Detail));
+        let Some(subject) = self
+            .subject_list
+            .selected()
+            .and_then(|selected| self.subjects.get(selected))
+        else {
+            frame.render_widget(block, area);
+            return;
+        };
+        let mut title = format!(
+            "{} | {}{} | weight {}",
+            subject.subject_name,
+            subject.score_mapping_list_id,
+            if subject.elective { " Elective" } else { "" },
+            subject.weight
+        );
+        if let Some(reason) = subject.exclusion_reason() {
+            title += &format!(" | not in GPA: {reason}");
+        }
+        let mut rows = Vec::new();
+        project_rows(
+            &mut rows,
+            &subject.evaluation_projects,
+            0,
+            self.tasks,
+            &self.config.colors,
+        );
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Fill(1),
+                Constraint::Length(12),
+                Constraint::Length(6),
+                Constraint::Length(5),
+                Constraint::Length(16),
+            ],
+        )
+        .header(Row::new(["Name", "Score", "Level", "GPA", "Proportion"]).bold())
+        .block(block.title(title))
+        .row_highlight_style(Modifier::REVERSED);
+        let table = if subject.in_gpa {
+            table
+        } else {
+            table.dark_gray()
+        };
+        frame.render_stateful_widget(table, area, &mut self.detail);
+    }
+
+    fn draw_footer(&self, frame: &mut Frame, area: Rect) {
+        let calculated_gpa = calculate_gpa(&self.subjects);
+        let gpa = if self.gpa.is_nan() {
+            "Unreleased".to_string()
+        } else {
+            self.gpa.to_string()
+        };
+        let summary = Line::from(format!(
+            "GPA: {gpa} | Calculated GPA: {:.2} / {:.2} | Calculated Unweighted GPA: {:.2} / {:.2}",
+            calculated_gpa.weighted_gpa,
+            calculated_gpa.max_gpa,
+            calculated_gpa.unweighted_gpa,
+            calculated_gpa.unweighted_max_gpa
+        ));
+        let help = if self.status.is_empty() {
+            Line::from("↑↓ move  enter open  ← back  t tasks  s semester  r refresh  q quit")
+                .dark_gray()
+        } else {
+            Line::from(self.status.clone()).yellow()
+        };
+        frame.render_widget(
+            Paragraph::new(vec![summary, help]).block(Block::bordered()),
+            area,
+        );
+    }
+
+    fn draw_semesters(&mut self, frame: &mut Frame, area: Rect) {
+        let height = (self.semesters.len() as u16 + 2).min(area.height);
+        let [area] = Layout::vertical([Constraint::Length(height)])
+            .flex(ratatui::layout::Flex::Center)
+            .areas(area);
+        let [area] = Layout::horizontal([Constraint::Length(32)])
+            .flex(ratatui::layout::Flex::Center)
+            .areas(area);
+        let items: Vec<ListItem> = self
+            .semesters
+            .iter()
+            .map(|semester| {
+                let name = Self::semester_name(semester);
+                if semester.is_now {
+                    ListItem::new(format!("{name} (current)"))
+                } else {
+                    ListItem::new(name)
+                }
+            })
+            .collect();
+        let list = List::new(items)
+            .block(
+                Block::bordered()
+                    .title("Semester")
+                    .border_style(self.border_style(Focus::Semesters)),
+            )
+            .highlight_style(Modifier::REVERSED);
+        frame.render_widget(Clear, area);
+        frame.render_stateful_widget(list, area, &mut self.semester_list);
+    }
+}
+
+/// Rows for the projects with a score, their sub-projects indented below
+/// them, and their tasks if `tasks` is set.
+fn project_rows(
+    rows: &mut Vec<Row<'static>>,
+    evaluation_projects: &[EvaluationProject],
+    depth: usize,
+    tasks: bool,
+    color_scheme: &ColorScheme,
+) {
+    let indent = "  ".repeat(depth);
+    for evaluation_project in evaluation_projects {
+        if evaluation_project.score_is_null {
+            continue;
+        }
+        rows.push(
+            Row::new([
+                format!("{indent}{}", evaluation_project.evaluation_project_e_name),
+                round_score(evaluation_project.score, 1).to_string(),
+                evaluation_project.score_level.clone(),
+                evaluation_project.gpa.to_string(),
+                format!(
+                    "{}% ({}%)",
+                    round_score(evaluation_project.adjusted_proportion, 2),
+                    round_score(evaluation_project.proportion, 2)
+                ),
+            ])
+            .style(level_style(&evaluation_project.score_level, color_scheme)),
+        );
+        if tasks {
+            let learning_tasks = evaluation_project
+                .learning_task_and_exam_list
+                .iter()
+                .zip(evaluation_project.task_proportions());
+            for (learning_task, proportion) in learning_tasks {
+                let (Some(score), Some(proportion)) = (learning_task.score, proportion) else {
+                    continue;
+                };
+                let percentage = round_score(score / learning_task.total_score * 100.0, 2);
+                rows.push(
+                    Row::new([
+                        format!("{indent}  - {}", learning_task.name),
+                        format!("{score} / {}", learning_task.total_score),
+                        format!("{percentage}%"),
+                        String::new(),
+                        format!("{}%", round_score(proportion, 2)),
+                    ])
+                    .dark_gray(),
+                );
+            }
+        }
+        project_rows(
+            rows,
+            &evaluation_project.evaluation_project_list,
+            depth + 1,
+            tasks,
+            color_scheme,
+        );
+    }
+}
+
+/// The color of a score level, from the same color scheme as the tables.
+fn level_style(score_level: &str, color_scheme: &ColorScheme) -> Style {
+    let color = match score_level.chars().next() {
+        Some('A') => &color_scheme.a_color,
+        Some('B') => &color_scheme.b_color,
+        Some('C') => &color_scheme.c_color,
+        Some('D') => &color_scheme.d_color,
+        Some('F') => &color_scheme.f_color,
+        _ => &color_scheme.text_color,
+    };
+    let style = Style::new().fg(to_ratatui_color(color));
+    if score_level == "A+" || score_level == "F" {
+        style.bold()
+    } else {
+        style
+    }
+}
+
+fn to_ratatui_color(color: &str) -> Color {
+    match colored::Color::from_str(color).unwrap_or(colored::Color::White) {
+        colored::Color::Black => Color::Black,
+        colored::Color::Red => Color::Red,
+        colored::Color::Green => Color::Green,
+        colored::Color::Yellow => Color::Yellow,
+        colored::Color::Blue => Color::Blue,
+        colored::Color::Magenta => Color::Magenta,
+        colored::Color::Cyan => Color::Cyan,
+        colored::Color::White => Color::Gray,
+        colored::Color::BrightBlack => Color::DarkGray,
+        colored::Color::BrightRed => Color::LightRed,
+        colored::Color::BrightGreen => Color::LightGreen,
+        colored::Color::BrightYellow => Color::LightYellow,
+        colored::Color::BrightBlue => Color::LightBlue,
+        colored::Color::BrightMagenta => Color::LightMagenta,
+        colored::Color::BrightCyan => Color::LightCyan,
+        colored::Color::BrightWhite => Color::White,
+        colored::Color::TrueColor { r, g, b } => Color::Rgb(r, g, b),
+    }
+}
@@ -0,0 +1,307 @@
+use crate::{
+    client::{ApiClient, SchoolisApi},
+    config::{Config, Hooks, Rules},
+    error::Error,
+    fetch_subjects,
+    gpa::{calculate_gpa, get_gpa, ScoreMappingConfig, ScoreMappingId},
+    history::{Diff, History},
+    relogin,
+    report::GradeReport,
+    semester::Semester,
+};
+use chrono::Local;
+use log::{info, warn};
+use std::{collections::HashMap, process::Stdio, sync::Arc, time::Duration};
+use tokio::{io::AsyncWriteExt, process::Command};
+
+/// The longest wait between failed polls, in intervals.
+const MAX_BACKOFF: u32 = 8;
+
+/// What the watcher does after a poll.
+#[derive(Debug, PartialEq)]
+enum Step {
+    /// Poll again after this long
+    Wait(Duration),
+    /// Log in again, then poll right away
+    Relogin,
+}
+
+/// Decides what follows a poll. A rejected session is logged in again once
+/// per run of failures. Other server, network and parse failures, and
+/// rejections that persist after logging in, are retried on the next tick
+/// with the wait doubling up to [`MAX_BACKOFF`] intervals.
+struct Backoff {
+    interval: Duration,
+    failures: u32,
+    relogged_in: bool,
+}
+
+impl Backoff {
+    fn new(interval: Duration) -> Backoff {
+        Backoff {
+            interval,
+            failures: 0,
+            relogged_in: false,
+        }
+    }
+
+    fn succeeded(&mut self) -> Step {
+        self.failures = 0;
+        self.relogged_in = false;
+        Step::Wait(self.interval)
+    }
+
+    /// The step after a failure, or the error back if retrying can't help.
+    fn failed(&mut self, err: Error) -> Result<Step, Error> {
+        match &err {
+            Error::Api(err, _) if err.needs_login() && !self.relogged_in => {
+                info!("Session rejected: {err}");
+                self.relogged_in = true;
+                return Ok(Step::Relogin);
+            }
+            Error::Api(..) | Error::Network(..) | Error::Parse(..) => {}
+            _ => return Err(err),
+        }
+        self.failures += 1;
+        let factor = 2u32.saturating_pow(self.failures - 1).min(MAX_BACKOFF);
+        let wait = self.interval * factor;
+        warn!(
+            "Poll failed, trying again in {}: {err}",
+            humantime::format_duration(wait)
+        );
+        Ok(Step::Wait(wait))
+    }
+}
+
+/// Polls the semester every `interval` until interrupted, printing what
+/// changed since the previous poll and running the hooks.
+pub async fn watch(
+    api_client: &ApiClient,
+    client: &Arc<dyn SchoolisApi>,
+    semester: &Semester,
+    score_mapping_lists: &Arc<HashMap<ScoreMappingId, Vec<ScoreMappingConfig>>>,
+    config: &Config,
+    hooks: &Hooks,
+    interval: Duration,
+) -> Result<(), Error> {
+    println!(
+        "Watching for new scores every {}, press Ctrl+C to stop",
+        humantime::format_duration(interval)
+    );
+    let mut previous: Option<GradeReport> = None;
+    let mut backoff = Backoff::new(interval);
+    loop {
+        let mut step = match poll(client, semester, score_mapping_lists, &config.rules).await {
+            Ok(report) => {
+                if let Some(previous) = &previous {
+                    let diff = Diff::new(previous, &report);
+                    if !diff.changes.is_empty() {
+                        println!("{}", Local::now().format("%Y-%m-%d %H:%M"));
+                        diff.print(config);
+                        run_hooks(hooks, &diff).await;
+                    }
+                }
+                if config.history {
+                    if let Err(err) = History::open().and_then(|history| history.save(&report)) {
+                        warn!("Failed to update grade history: {err}");
+                    }
+                }
+                previous = Some(report);
+                backoff.succeeded()
+            }
+            Err(err) => backoff.failed(err)?,
+        };
+        if step == Step::Relogin {
+            match relogin(api_client).await.and_then(|()| api_client.save()) {
+                Ok(()) => continue,
+                // e.g. the server is unreachable while logging in
+                Err(err) => step = backoff.failed(err)?,
+            }
+        }
+        if let Step::Wait(wait) = step {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+async fn poll(
+    client: &Arc<dyn SchoolisApi>,
+    semester: &Semester,
+    score_mapping_lists: &Arc<HashMap<ScoreMappingId, Vec<ScoreMappingConfig>>>,
+    rules: &Rules,
+) -> Result<GradeReport, Error> {
+    info!("Polling scores");
+    let subjects = fetch_subjects(client, semester.id, score_mapping_lists, rules).await?;
+    let gpa = get_gpa(&**client, semester.id).await?;
+    Ok(GradeReport::new(
+        semester,
+        &subjects,
+        gpa,
+        &calculate_gpa(&subjects),
+    ))
+}
+
+/// Runs every configured hook, hook failures are only logged.
+async fn run_hooks(hooks: &Hooks, diff: &Diff) {
+    let changes = match serde_json::to_string(diff) {
+        Ok(changes) => changes,
+        Err(err) => {
+            warn!("Failed to serialize changes: {err}");
+            return;
+        }
+    };
+    if let Some(command) = &hooks.command {
+        if let Err(err) = run_command(command, &changes).await {
+            warn!("Hook `{command}` failed: {err}");
+        }
+    }
+    if let Some(url) = &hooks.webhook {
+        if let Err(err) = post_webhook(url, &changes).await {
+            warn!("Webhook {url} failed: {err}");
+        }
+    }
+}
+
+fn shell(command: &str) -> Command {
+    if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C").arg(command);
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c").arg(command);
+        shell
+    }
+}
+
+async fn run_command(command: &str, changes: &str) -> Result<(), Error> {
+    info!("Running hook `{command}`");
+    let mut child = shell(command).stdin(Stdio::piped()).spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(changes.as_bytes()).await?;
+        // Dropping stdin closes it, so the command sees the end of input
+    }
+    let status = child.wait().await?;
+    if !status.success() {
+        return Err(std::io::Error::other(format!("exited with {status}")).into());
+    }
+    Ok(())
+}
+
+async fn post_webhook(url: &str, changes: &str) -> Result<(), Error> {
+    info!("Posting changes to {url}");
+    reqwest::Client::new()
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(changes.to_string())
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{client::ApiError, gpa::default_score_mapping_lists};
+    use async_trait::async_trait;
+    use serde_json::{json, Value};
+    use std::sync::Mutex;
+
+    /// A server with no subjects that answers every request with `state`.
+    struct FakeApi {
+        state: Mutex<i32>,
+    }
+
+    impl FakeApi {
+        fn respond(&self, path: &str) -> Value {
+            let state = *self.state.lock().unwrap();
+            if state != 0 {
+                return json!({ "state": state, "msg": "rejected" });
+            }
+            let data = if path.starts_with("DynamicScore/GetStuSemesterDynamicScore") {
+                json!({ "studentSemesterDynamicScoreBasicDtos": [] })
+            } else if path.starts_with("DynamicScore/GetGpa") {
+                Value::Null
+            } else {
+                json!([])
+            };
+            json!({ "state": 0, "data": data })
+        }
+    }
+
+    #[async_trait]
+    impl SchoolisApi for FakeApi {
+        async fn get(&self, path: &str) -> Result<Value, ApiError> {
+            Ok(self.respond(path))
+        }
+
+        async fn post(&self, path: &str, _body: &Value) -> Result<Value, ApiError> {
+            Ok(self.respond(path))
+        }
+    }
+
+    const INTERVAL: Duration = Duration::from_secs(60);
+
+    async fn step(api: &Arc<FakeApi>, state: i32, backoff: &mut Backoff) -> Step {
+        *api.state.lock().unwrap() = state;
+        let client: Arc<dyn SchoolisApi> = api.clone();
+        let semester: Semester = serde_json::from_value(json!({
+            "id": 1,
+            "year": 2024,
+            "semester": 1,
+            "isNow": true,
+            "startDate": "2024-08-19T00:00:00",
+            "endDate": "2025-01-17T00:00:00",
+        }))
+        .unwrap();
+        let lists = Arc::new(default_score_mapping_lists());
+        match poll(&client, &semester, &lists, &Rules::default()).await {
+            Ok(_) => backoff.succeeded(),
+            Err(err) => backoff.failed(err).unwrap(),
+        }
+    }
+
+    #[tokio::test]
+    async fn server_errors_back_off_without_logging_in() {
+        let api = Arc::new(FakeApi {
+            state: Mutex::new(0),
+        });
+        let mut backoff = Backoff::new(INTERVAL);
+        assert_eq!(step(&api, 0, &mut backoff).await, Step::Wait(INTERVAL));
+        for factor in [1, 2, 4, 8, 8] {
+            assert_eq!(
+                step(&api, 500, &mut backoff).await,
+                Step::Wait(INTERVAL * factor)
+            );
+        }
+        assert_eq!(step(&api, 0, &mut backoff).await, Step::Wait(INTERVAL));
+        assert_eq!(step(&api, 500, &mut backoff).await, Step::Wait(INTERVAL));
+    }
+
+    #[tokio::test]
+    async fn rejected_sessions_log_in_once_per_run_of_failures() {
+        let api = Arc::new(FakeApi {
+            state: Mutex::new(0),
+        });
+        let mut backoff = Backoff::new(INTERVAL);
+        assert_eq!(step(&api, 401, &mut backoff).await, Step::Relogin);
+        // Still rejected after logging in, so wait instead of prompting again
+        assert_eq!(step(&api, 401, &mut backoff).await, Step::Wait(INTERVAL));
+        assert_eq!(
+            step(&api, 403, &mut backoff).await,
+            Step::Wait(INTERVAL * 2)
+        );
+        assert_eq!(step(&api, 0, &mut backoff).await, Step::Wait(INTERVAL));
+        assert_eq!(step(&api, 403, &mut backoff).await, Step::Relogin);
+    }
+
+    #[test]
+    fn other_errors_stop_watching() {
+        let mut backoff = Backoff::new(INTERVAL);
+        assert!(matches!(
+            backoff.failed(Error::login("wrong password")),
+            Err(Error::Login(..))
+        ));
+    }
+}
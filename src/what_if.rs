@@ -0,0 +1,449 @@
+use crate::{
+    colorize,
+    config::Config,
+    error::Error,
+    gpa::*,
+    round_score,
+    subject::{adjust_proportions, get_subject_score, EvaluationProject, LearningTask, Subject},
+};
+use serde::{de, Deserialize, Deserializer};
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+    str::FromStr,
+};
+use tabled::{
+    settings::{object::Rows, Remove, Style},
+    Table,
+};
+
+/// Hypothetical scores, read from a scenario file or the command line.
+///
+/// Names are used as they are, `project` is a list of names from the top
+/// level down for a sub-project.
+///
+/// ```toml
+/// [[task]]
+/// subject = "AP Calculus BC"
+/// project = ["Summative", "Tests"]
+/// name = "Unit 5 Test"
+/// score = 42
+/// total_score = 50
+///
+/// [[project]]
+/// subject = "English"
+/// project = "Reading / Writing"
+/// score = 85
+/// ```
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct Scenario {
+    #[serde(rename = "task")]
+    pub tasks: Vec<TaskOverride>,
+    #[serde(rename = "project")]
+    pub projects: Vec<ProjectOverride>,
+}
+
+/// Sets the score of a task, adding the task if it doesn't exist.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct TaskOverride {
+    pub subject: String,
+    /// Evaluation project names from the top level down
+    #[serde(deserialize_with = "project_path")]
+    pub project: Vec<String>,
+    pub name: String,
+    pub score: f64,
+    #[serde(default = "default_total_score")]
+    pub total_score: f64,
+}
+
+/// Sets the score of an evaluation project, e.g. one that is unreleased.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct ProjectOverride {
+    pub subject: String,
+    /// Evaluation project names from the top level down
+    #[serde(deserialize_with = "project_path")]
+    pub project: Vec<String>,
+    pub score: f64,
+}
+
+fn default_total_score() -> f64 {
+    100.0
+}
+
+/// Reads a project name, or a list of them for a sub-project.
+fn project_path<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum ProjectPath {
+        Name(String),
+        Path(Vec<String>),
+    }
+    match ProjectPath::deserialize(deserializer)? {
+        ProjectPath::Name(name) => Ok(vec![name]),
+        ProjectPath::Path(path) if path.is_empty() => {
+            Err(de::Error::custom("project can't be an empty list"))
+        }
+        ProjectPath::Path(path) => Ok(path),
+    }
+}
+
+impl Scenario {
+    pub fn load(path: &Path) -> Result<Scenario, Error> {
+        let scenario = std::fs::read_to_string(path)?;
+        Scenario::parse(&scenario)
+            .map_err(|err| Error::input(format!("invalid scenario {}: {err}", path.display())))
+    }
+
+    fn parse(scenario: &str) -> Result<Scenario, toml::de::Error> {
+        toml::from_str(scenario)
+    }
+}
+
+/// Splits a command line path on `/`, where `\/` is a `/` inside a name
+/// and `\\` a backslash.
+fn split_path(path: &str) -> Vec<String> {
+    let mut names = vec![String::new()];
+    let mut chars = path.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(escaped @ ('/' | '\\')) => names.last_mut().unwrap().push(escaped),
+                Some(other) => names.last_mut().unwrap().extend(['\\', other]),
+                None => names.last_mut().unwrap().push('\\'),
+            },
+            '/' => names.push(String::new()),
+            c => names.last_mut().unwrap().push(c),
+        }
+    }
+    names.iter().map(|name| name.trim().to_string()).collect()
+}
+
+/// Names joined the way they are shown in errors.
+fn display_path(path: &[String]) -> String {
+    path.join(" > ")
+}
+
+/// Parses `SUBJECT/PROJECT[/SUB_PROJECT]/TASK=SCORE[/TOTAL]`.
+impl FromStr for TaskOverride {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (path, score) = s
+            .rsplit_once('=')
+            .ok_or("expected SUBJECT/PROJECT/TASK=SCORE[/TOTAL]")?;
+        let mut path = split_path(path);
+        if path.len() < 3 {
+            return Err("expected SUBJECT/PROJECT/TASK=SCORE[/TOTAL]".to_string());
+        }
+        let (score, total_score) = match score.split_once('/') {
+            Some((score, total_score)) => (score, total_score),
+            None => (score, "100"),
+        };
+        let name = path.pop().unwrap();
+        let subject = path.remove(0);
+        Ok(TaskOverride {
+            subject,
+            project: path,
+            name,
+            score: parse_score(score)?,
+            total_score: parse_score(total_score)?,
+        })
+    }
+}
+
+/// Parses `SUBJECT/PROJECT[/SUB_PROJECT]=SCORE`.
+impl FromStr for ProjectOverride {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (path, score) = s.rsplit_once('=').ok_or("expected SUBJECT/PROJECT=SCORE")?;
+        let mut path = split_path(path);
+        if path.len() < 2 {
+            return Err("expected SUBJECT/PROJECT=SCORE".to_string());
+        }
+        let subject = path.remove(0);
+        Ok(ProjectOverride {
+            subject,
+            project: path,
+            score: parse_score(score)?,
+        })
+    }
+}
+
+fn parse_score(score: &str) -> Result<f64, String> {
+    score
+        .trim()
+        .parse()
+        .map_err(|_| format!("{score} is not a number"))
+}
+
+/// Applies the scenario to `subjects` and recalculates the affected
+/// projects and subjects the same way as the fetched ones.
+pub fn apply(
+    subjects: &mut [Subject],
+    scenario: &Scenario,
+    score_mapping_lists: &HashMap<ScoreMappingId, Vec<ScoreMappingConfig>>,
+) -> Result<(), Error> {
+    let mut changed_subjects = HashSet::new();
+    for task_override in &scenario.tasks {
+        let index = find_subject(subjects, &task_override.subject)?;
+        changed_subjects.insert(index);
+        let subject = &mut subjects[index];
+        let path = &task_override.project;
+        let evaluation_project = find_project(&mut subject.evaluation_projects, path)
+            .ok_or_else(|| no_project(&task_override.subject, &task_override.project))?;
+        let tasks = &mut evaluation_project.learning_task_and_exam_list;
+        match tasks
+            .iter_mut()
+            .find(|task| task.name.eq_ignore_ascii_case(&task_override.name))
+        {
+            Some(task) => {
+                task.score = Some(task_override.score);
+                task.total_score = task_override.total_score;
+            }
+            None => tasks.push(LearningTask {
+                name: task_override.name.clone(),
+                score: Some(task_override.score),
+                total_score: task_override.total_score,
+            }),
+        }
+        if let Some(score) = evaluation_project.score_from_tasks() {
+            set_project_score(evaluation_project, score, &subject.score_mapping_list);
+        }
+        recalculate_parents(subject, path);
+    }
+
+    for project_override in &scenario.projects {
+        let index = find_subject(subjects, &project_override.subject)?;
+        changed_subjects.insert(index);
+        let subject = &mut subjects[index];
+        let path = &project_override.project;
+        let evaluation_project = find_project(&mut subject.evaluation_projects, path)
+            .ok_or_else(|| no_project(&project_override.subject, &project_override.project))?;
+        set_project_score(
+            evaluation_project,
+            project_override.score,
+            &subject.score_mapping_list,
+        );
+        recalculate_parents(subject, path);
+    }
+
+    // Unchanged subjects keep the server's score
+    for index in changed_subjects {
+        recalculate_subject(&mut subjects[index], score_mapping_lists);
+    }
+    Ok(())
+}
+
+fn find_subject(subjects: &[Subject], name: &str) -> Result<usize, Error> {
+    subjects
+        .iter()
+        .position(|subject| subject.subject_name.eq_ignore_ascii_case(name))
+        .ok_or_else(|| Error::input(format!("there is no subject named {name}")))
+}
+
+fn find_project<'a>(
+    evaluation_projects: &'a mut [EvaluationProject],
+    path: &[String],
+) -> Option<&'a mut EvaluationProject> {
+    let (name, rest) = path.split_first()?;
+    let evaluation_project = evaluation_projects.iter_mut().find(|evaluation_project| {
+        evaluation_project
+            .evaluation_project_e_name
+            .eq_ignore_ascii_case(name)
+    })?;
+    if rest.is_empty() {
+        return Some(evaluation_project);
+    }
+    find_project(&mut evaluation_project.evaluation_project_list, rest)
+}
+
+fn no_project(subject: &str, project: &[String]) -> Error {
+    Error::input(format!(
+        "{subject} has no evaluation project {}",
+        display_path(project)
+    ))
+}
+
+fn set_project_score(
+    evaluation_project: &mut EvaluationProject,
+    score: f64,
+    score_mapping_list: &[ScoreMappingConfig],
+) {
+    evaluation_project.score = score;
+    evaluation_project.score_is_null = false;
+    evaluation_project.gpa = gpa_from_score(score, score_mapping_list);
+    evaluation_project.score_level = score_level_from_score(score, score_mapping_list);
+}
+
+/// Recalculates the projects above `path` from their sub-projects,
+/// innermost first.
+fn recalculate_parents(subject: &mut Subject, path: &[String]) {
+    for depth in (1..path.len()).rev() {
+        let Some(parent) = find_project(&mut subject.evaluation_projects, &path[..depth]) else {
+            continue;
+        };
+        let scored: Vec<&EvaluationProject> = parent
+            .evaluation_project_list
+            .iter()
+            .filter(|evaluation_project| !evaluation_project.score_is_null)
+            .collect();
+        let total_proportion: f64 = scored
+            .iter()
+            .map(|evaluation_project| evaluation_project.proportion)
+            .sum();
+        if scored.is_empty() || total_proportion == 0.0 {
+            continue;
+        }
+        let score = scored
+            .iter()
+            .map(|evaluation_project| evaluation_project.score * evaluation_project.proportion)
+            .sum::<f64>()
+            / total_proportion;
+        set_project_score(parent, score, &subject.score_mapping_list);
+    }
+}
+
+fn recalculate_subject(
+    subject: &mut Subject,
+    score_mapping_lists: &HashMap<ScoreMappingId, Vec<ScoreMappingConfig>>,
+) {
+    adjust_proportions(&mut subject.evaluation_projects);
+    let extra_credit = if subject.extra_credit.is_finite() {
+        subject.extra_credit
+    } else {
+        0.0
+    };
+    subject.total_score = get_subject_score(&subject.evaluation_projects) + extra_credit;
+    subject.update_gpa(score_mapping_lists);
+}
+
+fn format_result(subject: &Subject, config: &Config) -> String {
+    if subject.total_score.is_nan() {
+        return "-".to_string();
+    }
+    format!(
+        "{} ({}, {})",
+        round_score(subject.total_score, 1),
+        colorize(&subject.score_level, &subject.score_level, &config.colors),
+        subject.gpa
+    )
+}
+
+pub fn print_comparison(current: &[Subject], simulated: &[Subject], config: &Config) {
+    let mut data = vec![(
+        "Subject".to_string(),
+        "Current".to_string(),
+        "What-if".to_string(),
+    )];
+    for (current, simulated) in current.iter().zip(simulated) {
+        let current_result = format_result(current, config);
+        let simulated_result = format_result(simulated, config);
+        let name = if current_result == simulated_result {
+            current.subject_name.clone()
+        } else {
+            format!("* {}", current.subject_name)
+        };
+        data.push((name, current_result, simulated_result));
+    }
+    let table = Table::new(data)
+        .with(Remove::row(Rows::first()))
+        .with(Style::rounded())
+        .to_string();
+    println!("{table}");
+
+    let current_gpa = calculate_gpa(current);
+    let simulated_gpa = calculate_gpa(simulated);
+    println!(
+        "Calculated GPA: {:.2} -> {:.2} / {:.2}",
+        current_gpa.weighted_gpa, simulated_gpa.weighted_gpa, simulated_gpa.max_gpa
+    );
+    println!(
+        "Calculated Unweighted GPA: {:.2} -> {:.2} / {:.2}",
+        current_gpa.unweighted_gpa, simulated_gpa.unweighted_gpa, simulated_gpa.unweighted_max_gpa
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn scenario_names_are_not_split() {
+        let scenario = Scenario::parse(
+            r#"
+            [[task]]
+            subject = "AP Calculus BC"
+            project = "Summative / Tests"
+            name = "Unit 5 Test"
+            score = 42
+            total_score = 50
+
+            [[project]]
+            subject = "English"
+            project = ["Reading/Writing", "Essays"]
+            score = 85
+            "#,
+        )
+        .unwrap();
+        assert_eq!(scenario.tasks[0].project, path(&["Summative / Tests"]));
+        assert_eq!(scenario.tasks[0].name, "Unit 5 Test");
+        assert_eq!(
+            scenario.projects[0].project,
+            path(&["Reading/Writing", "Essays"])
+        );
+        assert!(
+            Scenario::parse("[[project]]\nsubject = \"English\"\nproject = []\nscore = 1").is_err()
+        );
+    }
+
+    #[test]
+    fn command_line_slashes_can_be_escaped() {
+        let task: TaskOverride = r"AP Calculus BC/Summative \/ Tests/Unit 5 Test=42/50"
+            .parse()
+            .unwrap();
+        assert_eq!(task.subject, "AP Calculus BC");
+        assert_eq!(task.project, path(&["Summative / Tests"]));
+        assert_eq!(task.name, "Unit 5 Test");
+        assert_eq!((task.score, task.total_score), (42.0, 50.0));
+
+        let project: ProjectOverride = r"English/Reading\/Writing/Essays=85".parse().unwrap();
+        assert_eq!(project.project, path(&["Reading/Writing", "Essays"]));
+        assert_eq!(split_path(r"a\\b/c\d"), path(&[r"a\b", r"c\d"]));
+        assert!("English=85".parse::<ProjectOverride>().is_err());
+    }
+
+    #[test]
+    fn applies_to_projects_with_slashes() {
+        let score_mapping_lists = default_score_mapping_lists();
+        let mut subjects = vec![Subject::for_tests(
+            "AP Calculus BC",
+            vec![
+                EvaluationProject::for_tests("Summative / Tests", 100.0, Some(80.0)).with_task(
+                    "Unit 4 Test",
+                    40.0,
+                    50.0,
+                ),
+            ],
+        )];
+        let scenario = Scenario::parse(
+            r#"
+            [[task]]
+            subject = "AP Calculus BC"
+            project = "Summative / Tests"
+            name = "Unit 5 Test"
+            score = 50
+            total_score = 50
+            "#,
+        )
+        .unwrap();
+        apply(&mut subjects, &scenario, &score_mapping_lists).unwrap();
+        assert_eq!(subjects[0].evaluation_projects[0].score, 90.0);
+        assert_eq!(subjects[0].total_score, 90.0);
+    }
+}